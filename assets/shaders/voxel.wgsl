@@ -0,0 +1,148 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::pbr_types
+
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::shadows
+#import bevy_pbr::mesh_functions
+#import bevy_pbr::pbr_functions
+
+struct VoxelMaterial {
+    flags: u32,
+    alpha_cutoff: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> material: VoxelMaterial;
+@group(1) @binding(1)
+var block_textures: texture_2d_array<f32>;
+@group(1) @binding(2)
+var block_sampler: sampler;
+
+// Keep in sync with `PackedVertex`
+let UNITS: f32 = 16.0;
+
+// Brightness of a corner with 0 to 3 open blocks around it
+let AO_0: f32 = 0.45;
+let AO_1: f32 = 0.65;
+let AO_2: f32 = 0.82;
+let AO_3: f32 = 1.0;
+// Brightness lost for every level of light below the maximum
+let LIGHT_FALLOFF: f32 = 0.8;
+let MAX_LIGHT: f32 = 15.0;
+// Brightness of blocks that no light reaches, so caves are not pitch black
+let MIN_BRIGHTNESS: f32 = 0.03;
+
+struct Vertex {
+    @location(0) packed: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) brightness: f32,
+    @location(4) @interpolate(flat) layer: u32,
+};
+
+// Normals of the faces, in the order of `Faces::ALL`
+fn face_normal(face_id: u32) -> vec3<f32> {
+    switch face_id {
+        case 0u: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1u: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 2u: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4u: { return vec3<f32>(0.0, 0.0, 1.0); }
+        case 5u: { return vec3<f32>(0.0, 0.0, -1.0); }
+        default: { return vec3<f32>(0.0, 1.0, 0.0); }
+    }
+}
+
+// Texture coordinates are in sixteenths up to 1, and in whole textures above that
+fn tex_coord(packed: u32) -> f32 {
+    if packed > u32(UNITS) {
+        return f32(packed + 1u - u32(UNITS));
+    }
+    return f32(packed) / UNITS;
+}
+
+fn ambient_occlusion(ao: u32) -> f32 {
+    switch ao {
+        case 0u: { return AO_0; }
+        case 1u: { return AO_1; }
+        case 2u: { return AO_2; }
+        default: { return AO_3; }
+    }
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let position = vec3<f32>(
+        f32(vertex.packed.x & 0x3ffu),
+        f32((vertex.packed.x >> 10u) & 0x3ffu),
+        f32((vertex.packed.x >> 20u) & 0x3ffu),
+    ) / UNITS;
+    let ao = vertex.packed.x >> 30u;
+    let u = tex_coord(vertex.packed.y & 0x3fu);
+    let v = tex_coord((vertex.packed.y >> 6u) & 0x3fu);
+    let layer = (vertex.packed.y >> 12u) & 0xffu;
+    let face_id = (vertex.packed.y >> 20u) & 0x7u;
+    // Light comes in quarter levels
+    let light = f32((vertex.packed.y >> 23u) & 0x3fu) / 4.0;
+
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_normal_local_to_world(face_normal(face_id));
+    out.uv = vec2<f32>(u, v);
+    out.layer = layer;
+    out.brightness = ambient_occlusion(ao)
+        * max(pow(LIGHT_FALLOFF, MAX_LIGHT - light), MIN_BRIGHTNESS);
+    return out;
+}
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) brightness: f32,
+    @location(4) @interpolate(flat) layer: u32,
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    var color = textureSample(block_textures, block_sampler, in.uv, i32(in.layer));
+    color = vec4<f32>(color.rgb * in.brightness, color.a);
+
+    var pbr_input: PbrInput = pbr_input_new();
+    pbr_input.material.base_color = color;
+    pbr_input.material.reflectance = 0.0;
+    pbr_input.material.metallic = 0.0;
+    pbr_input.material.perceptual_roughness = 1.0;
+    pbr_input.material.flags = material.flags;
+    pbr_input.material.alpha_cutoff = material.alpha_cutoff;
+
+    pbr_input.frag_coord = in.frag_coord;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(in.world_normal, false, in.is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = normalize(pbr_input.world_normal);
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+
+    var output_color = pbr(pbr_input);
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+#ifdef DEBAND_DITHER
+    var output_rgb = output_color.rgb;
+    output_rgb = pow(output_rgb, vec3<f32>(1.0 / 2.2));
+    output_rgb = output_rgb + screen_space_dither(in.frag_coord.xy);
+    output_rgb = pow(output_rgb, vec3<f32>(2.2));
+    output_color = vec4<f32>(output_rgb, output_color.a);
+#endif
+    return output_color;
+}
@@ -0,0 +1,130 @@
+use std::{error::Error, fmt};
+
+/// A property of a block type, such as the axis of a log, with every value it can take.
+///
+/// A block's state packs the value of each of its type's properties into a single number, with the
+/// first value of every property being the default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StateProperty {
+    pub name: &'static str,
+    pub values: &'static [&'static str],
+}
+
+impl StateProperty {
+    pub const AXIS: StateProperty = StateProperty {
+        name: "axis",
+        values: &["y", "x", "z"],
+    };
+    pub const FACING: StateProperty = StateProperty {
+        name: "facing",
+        values: &["north", "south", "east", "west"],
+    };
+    pub const HALF: StateProperty = StateProperty {
+        name: "half",
+        values: &["bottom", "top"],
+    };
+    pub const OPEN: StateProperty = StateProperty {
+        name: "open",
+        values: &["false", "true"],
+    };
+    /// Distance a fluid has flowed from its source, which is level 0
+    pub const LEVEL: StateProperty = StateProperty {
+        name: "level",
+        values: &["0", "1", "2", "3", "4", "5", "6", "7"],
+    };
+}
+
+/// Number of distinct states a block type with these properties has, or an error if they do not
+/// fit in a block's 16 bit state
+pub fn state_count(properties: &[StateProperty]) -> Result<u16, TooManyStates> {
+    properties.iter().try_fold(1u16, |count, property| {
+        u16::try_from(property.values.len())
+            .ok()
+            .and_then(|values| count.checked_mul(values))
+            .ok_or(TooManyStates)
+    })
+}
+
+/// The properties of a block type combine into more states than a block can hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TooManyStates;
+
+impl fmt::Display for TooManyStates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block has more than {} states", u16::MAX)
+    }
+}
+
+impl Error for TooManyStates {}
+
+/// Returns the value of the property `name` in `state`
+pub fn get_state_value(
+    properties: &[StateProperty],
+    state: u16,
+    name: &str,
+) -> Option<&'static str> {
+    state_values(properties, state)
+        .find(|(property, _)| *property == name)
+        .map(|(_, value)| value)
+}
+
+/// Returns `state` with the property `name` set to `value`, or None if the block type has no such
+/// property or value
+pub fn set_state_value(
+    properties: &[StateProperty],
+    state: u16,
+    name: &str,
+    value: &str,
+) -> Option<u16> {
+    let mut stride = 1;
+    for property in properties {
+        let count = property.values.len() as u16;
+        if property.name == name {
+            let index = property.values.iter().position(|v| *v == value)? as u16;
+            let current = state / stride % count;
+            return Some(state - current * stride + index * stride);
+        }
+        stride *= count;
+    }
+    None
+}
+
+/// Iterates over the (property, value) pairs of `state`
+pub fn state_values(
+    properties: &[StateProperty],
+    state: u16,
+) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+    let mut stride = 1;
+    properties.iter().map(move |property| {
+        let count = property.values.len() as u16;
+        let value = property.values[(state / stride % count) as usize];
+        stride *= count;
+        (property.name, value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_state_value, set_state_value, state_count, StateProperty, TooManyStates};
+
+    #[test]
+    fn test_state_packing() {
+        let properties = [StateProperty::AXIS, StateProperty::HALF];
+        assert_eq!(state_count(&properties), Ok(6));
+        assert_eq!(state_count(&[StateProperty::LEVEL; 5]), Ok(32768));
+        assert_eq!(state_count(&[StateProperty::LEVEL; 6]), Err(TooManyStates));
+        assert_eq!(get_state_value(&properties, 0, "axis"), Some("y"));
+
+        let state = set_state_value(&properties, 0, "half", "top").unwrap();
+        let state = set_state_value(&properties, state, "axis", "z").unwrap();
+        assert_eq!(get_state_value(&properties, state, "axis"), Some("z"));
+        assert_eq!(get_state_value(&properties, state, "half"), Some("top"));
+
+        let state = set_state_value(&properties, state, "axis", "x").unwrap();
+        assert_eq!(get_state_value(&properties, state, "axis"), Some("x"));
+        assert_eq!(get_state_value(&properties, state, "half"), Some("top"));
+
+        assert_eq!(set_state_value(&properties, state, "facing", "north"), None);
+        assert_eq!(set_state_value(&properties, state, "axis", "w"), None);
+    }
+}
@@ -1,6 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use super::StateProperty;
+
+/// How a block is drawn, which also decides whether it hides the faces of its neighbors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderType {
+    /// Not drawn at all, like air
+    Invisible,
+    /// Fully covers its neighbors' faces and blocks light
+    #[default]
+    Opaque,
+    /// Drawn with fully see-through pixels, like leaves
+    Cutout,
+    /// Drawn with partially see-through pixels, like glass
+    Transparent,
+}
+
 pub trait BlockType: Send + Sync {
-    fn get_name(&self) -> &'static str;
+    fn get_name(&self) -> &str;
     fn get_durability(&self) -> f32;
-    fn get_id(&self) -> u16;
-    fn get_code_name(&self) -> &'static str;
+    fn get_code_name(&self) -> &str;
+
+    /// Whether entities collide with this block
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn get_render_type(&self) -> RenderType {
+        RenderType::Opaque
+    }
+
+    /// Whether the block fills its whole cell. Blocks with a smaller model in the data pack, like
+    /// slabs or plants, return false so that they let light through and cast no ambient occlusion.
+    fn is_full_cube(&self) -> bool {
+        true
+    }
+
+    /// Block light level emitted by this block, from 0 to 15
+    fn get_light_emission(&self) -> u8 {
+        0
+    }
+
+    /// Whether the block is a fluid that flows into the air around it. Fluid blocks keep their
+    /// level in their state, so `StateProperty::LEVEL` has to be their only property.
+    fn is_fluid(&self) -> bool {
+        false
+    }
+
+    /// Properties that make up the state of this block type, such as the axis of a log
+    fn get_state_properties(&self) -> &'static [StateProperty] {
+        &[]
+    }
+
+    fn get_properties(&self) -> BlockProperties {
+        BlockProperties {
+            solid: self.is_solid(),
+            render_type: self.get_render_type(),
+            full_cube: self.is_full_cube(),
+            light_emission: self.get_light_emission(),
+            fluid: self.is_fluid(),
+        }
+    }
+}
+
+/// Copy of the properties of a `BlockType`, for code that looks them up for many blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockProperties {
+    pub solid: bool,
+    pub render_type: RenderType,
+    pub full_cube: bool,
+    pub light_emission: u8,
+    pub fluid: bool,
+}
+
+impl BlockProperties {
+    pub const AIR: BlockProperties = BlockProperties {
+        solid: false,
+        render_type: RenderType::Invisible,
+        full_cube: false,
+        light_emission: 0,
+        fluid: false,
+    };
+
+    /// Used for blocks that are not registered
+    pub const UNKNOWN: BlockProperties = BlockProperties {
+        solid: true,
+        render_type: RenderType::Opaque,
+        full_cube: true,
+        light_emission: 0,
+        fluid: false,
+    };
+
+    /// Whether the block fills its cell with opaque pixels, so it casts ambient occlusion and stops
+    /// light
+    #[inline]
+    pub fn is_opaque(&self) -> bool {
+        self.render_type == RenderType::Opaque && self.full_cube
+    }
+
+    /// Whether the block is drawn
+    #[inline]
+    pub fn is_visible(&self) -> bool {
+        self.render_type != RenderType::Invisible
+    }
+
+    /// Whether the player can target the block to mine it or build against it. Fluids are drawn
+    /// but reached through.
+    #[inline]
+    pub fn is_targetable(&self) -> bool {
+        self.is_visible() && !self.fluid
+    }
 }
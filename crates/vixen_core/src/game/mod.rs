@@ -1,6 +1,8 @@
+mod block_state;
 mod block_types;
 mod pause_menu;
 mod plugin;
 
+pub use block_state::*;
 pub use block_types::*;
 pub use plugin::*;
@@ -2,6 +2,7 @@ use bevy::prelude::*;
 
 use crate::{
     grab_mouse,
+    loader::Worldgen,
     player::Playing,
     release_mouse,
     ui::{spawn_button, MenuFont},
@@ -86,6 +87,7 @@ fn update_pause_menu(
         (&Interaction, &PauseMenuButton),
         (Changed<Interaction>, With<Button>),
     >,
+    mut worldgen: Option<ResMut<Worldgen>>,
 ) {
     for (interaction, button) in interaction_query.iter() {
         match button {
@@ -107,6 +109,13 @@ fn update_pause_menu(
             }
             PauseMenuButton::Quit => {
                 if let Interaction::Clicked = interaction {
+                    // Exiting the process skips the game state's exit systems, so save here.
+                    // If chunks fail to save, stay in the game rather than lose them.
+                    if let Some(worldgen) = worldgen.as_mut() {
+                        if !worldgen.save_all() {
+                            continue;
+                        }
+                    }
                     std::process::exit(0);
                 }
             }
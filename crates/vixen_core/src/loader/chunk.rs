@@ -1,21 +1,34 @@
 use bevy::utils::HashMap;
-use ndarray::Array3;
 
-use super::{ChunkCoord, MeshData};
+use super::{
+    fluid::fluid_edges,
+    model::BlockModel,
+    neighborhood::{quad_indices, ChunkNeighborhood},
+    texture::FaceTextures,
+    vertex::{facing, PackedVertex},
+    visibility::ChunkConnectivity,
+    BlockPropertyTable, BlockStorage, ChunkCoord, ChunkMeshData, LightStorage, MeshData,
+};
 pub const CHUNK_SIZE: (usize, usize, usize) = (32, 32, 32);
 
-#[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
 pub struct Block {
     pub id: u16,
+    /// Packed values of the block type's state properties, see `StateProperty`
+    pub state: u16,
 }
 
 impl Block {
     pub fn new(id: u16) -> Block {
-        Block { id }
+        Block { id, state: 0 }
+    }
+
+    pub fn with_state(id: u16, state: u16) -> Block {
+        Block { id, state }
     }
 
     pub fn air() -> Block {
-        Block { id: 0 }
+        Block { id: 0, state: 0 }
     }
 
     pub fn is_air(&self) -> bool {
@@ -23,11 +36,13 @@ impl Block {
     }
 }
 
-struct Faces;
-struct Face {
-    points: &'static [(i32, i32, i32); 4],
-    normal: (i32, i32, i32),
-    face_id: u8,
+pub(super) struct Faces;
+pub(super) struct Face {
+    pub points: &'static [(i32, i32, i32); 4],
+    pub normal: (i32, i32, i32),
+    pub face_id: u8,
+    /// Chunk axes as (normal, u, v), where u and v follow the face's texture coordinates
+    pub axes: (usize, usize, usize),
 }
 
 impl Faces {
@@ -35,40 +50,62 @@ impl Faces {
         points: &[(1, 0, 0), (1, 1, 0), (1, 1, 1), (1, 0, 1)],
         normal: (1, 0, 0),
         face_id: 0,
+        axes: (0, 2, 1),
     };
     pub const LEFT: &'static Face = &Face {
         points: &[(0, 0, 1), (0, 1, 1), (0, 1, 0), (0, 0, 0)],
         normal: (-1, 0, 0),
         face_id: 1,
+        axes: (0, 2, 1),
     };
     pub const BOTTOM: &'static Face = &Face {
         points: &[(1, 0, 0), (1, 0, 1), (0, 0, 1), (0, 0, 0)],
         normal: (0, -1, 0),
         face_id: 2,
+        axes: (1, 0, 2),
     };
     pub const TOP: &'static Face = &Face {
         points: &[(1, 1, 1), (1, 1, 0), (0, 1, 0), (0, 1, 1)],
         normal: (0, 1, 0),
         face_id: 3,
+        axes: (1, 0, 2),
     };
     pub const FRONT: &'static Face = &Face {
         points: &[(1, 0, 1), (1, 1, 1), (0, 1, 1), (0, 0, 1)],
         normal: (0, 0, 1),
         face_id: 4,
+        axes: (2, 0, 1),
     };
     pub const BACK: &'static Face = &Face {
         points: &[(0, 0, 0), (0, 1, 0), (1, 1, 0), (1, 0, 0)],
         normal: (0, 0, -1),
         face_id: 5,
+        axes: (2, 0, 1),
     };
+
+    /// All faces, in the order of their ids
+    pub const ALL: [&'static Face; 6] = [
+        Faces::RIGHT,
+        Faces::LEFT,
+        Faces::BOTTOM,
+        Faces::TOP,
+        Faces::FRONT,
+        Faces::BACK,
+    ];
 }
 
-pub type ChunkData = Option<Box<ndarray::Array3<Block>>>;
+pub type ChunkData = Option<BlockStorage>;
 
 pub struct Chunk {
     // coord: ChunkCoord,
     block_data: ChunkData,
+    light: LightStorage,
+    connectivity: ChunkConnectivity,
     needs_update: bool,
+    /// Set once the chunk differs from what the generator or the world save produced
+    modified: bool,
+    /// Air blocks next to fluid, which the fluid flows into once the chunk is in the world
+    fluid_edges: Vec<(usize, usize, usize)>,
 }
 
 impl Chunk {
@@ -76,7 +113,11 @@ impl Chunk {
         Chunk {
             // coord,
             block_data: None,
+            light: LightStorage::default(),
+            connectivity: ChunkConnectivity::ALL,
             needs_update: false,
+            modified: false,
+            fluid_edges: Vec::new(),
         }
     }
 
@@ -84,336 +125,196 @@ impl Chunk {
         Chunk {
             // coord,
             block_data: None,
+            light: LightStorage::default(),
+            connectivity: ChunkConnectivity::ALL,
             needs_update: true,
+            modified: false,
+            fluid_edges: Vec::new(),
         }
     }
 
-    pub fn from_data(_coord: ChunkCoord, data: Box<Array3<Block>>) -> Chunk {
+    pub fn from_data(_coord: ChunkCoord, data: BlockStorage) -> Chunk {
         Chunk {
             // coord,
             block_data: Some(data),
+            light: LightStorage::default(),
+            connectivity: ChunkConnectivity::ALL,
             needs_update: true,
+            modified: false,
+            fluid_edges: Vec::new(),
         }
     }
 
     fn add_face(
-        block_data: &Array3<Block>,
-        vertex_data: &mut VertexDataList,
-        indices: &mut Vec<u32>,
+        hood: &ChunkNeighborhood,
+        mesh: &mut MeshData,
         (i, j, k): (usize, usize, usize),
         face: &Face,
-        texture_map_info: &HashMap<u16, [[[f32; 2]; 4]; 6]>,
+        texture_map_info: &HashMap<Block, FaceTextures>,
     ) {
-        const FACE_INDICES: &[i32; 6] = &[0, 1, 2, 2, 3, 0];
-        let mut mesh_face_index_loc: [usize; 4] = [0; 4];
+        let start = mesh.vertices.len() as u32;
+        let texture = texture_map_info
+            .get(&hood.get((i as i32, j as i32, k as i32)))
+            .unwrap()[face.face_id as usize];
+        let ao = hood.face_ao((i, j, k), face);
+        let light = hood.face_light((i, j, k), face);
 
         for c in 0..4 {
-            let (fx, fy, fz) = face.points.get(c).unwrap();
-            let point_in_chunk_space = (i as i32 + fx, j as i32 + fy, k as i32 + fz);
-            mesh_face_index_loc[c] = vertex_data.0.len() as usize;
-
-            let face_tex_coords = texture_map_info
-                .get(&block_data.get((i, j, k)).unwrap().id)
-                .unwrap()[face.face_id as usize];
-
-            // vertices_normals_uvs.push(
-            //     (point_in_chunk_space.0 as u32)
-            //     | (point_in_chunk_space.1 as u32) << 6
-            //     | (point_in_chunk_space.2 as u32) << 12
-            //     | (face.normal.0 as u32) << 18
-            //     | (face.normal.1 as u32) << 19
-            //     | (face.normal.2 as u32) << 20
-            //     | face_tex_coords[c][0] << 21
-            //     | face_tex_coords[c][1] << 22);
-            vertex_data.0.push([
-                point_in_chunk_space.0 as f32,
-                point_in_chunk_space.1 as f32,
-                point_in_chunk_space.2 as f32,
-            ]);
-            vertex_data.1.push([
-                face.normal.0 as f32,
-                face.normal.1 as f32,
-                face.normal.2 as f32,
-            ]);
-            vertex_data
-                .2
-                .push([face_tex_coords[c][0], face_tex_coords[c][1]]);
+            let (fx, fy, fz) = face.points[c];
+            mesh.vertices.push(PackedVertex::new(
+                [
+                    (i as i32 + fx) as f32,
+                    (j as i32 + fy) as f32,
+                    (k as i32 + fz) as f32,
+                ],
+                face.face_id as usize,
+                (texture.uvs[c], texture.layer),
+                ao[c],
+                light[c],
+            ));
         }
 
-        for ind in FACE_INDICES.iter() {
-            indices.push(mesh_face_index_loc[*ind as usize] as u32);
+        mesh.indices.extend(quad_indices(start, ao));
+    }
+
+    /// Adds the quads of a block model that its neighbors do not hide. Models are lit evenly by the
+    /// light in their own cell and get no ambient occlusion.
+    pub(super) fn add_model(
+        hood: &ChunkNeighborhood,
+        mesh: &mut MeshData,
+        (i, j, k): (usize, usize, usize),
+        model: &BlockModel,
+    ) {
+        let block = hood.get((i as i32, j as i32, k as i32));
+        let light = hood.get_light((i as i32, j as i32, k as i32)).unwrap_or(0) * 4;
+
+        for quad in model.quads.iter() {
+            if let Some(face_id) = quad.cull_face {
+                if hood.is_covered((i, j, k), block, face_id, &quad.mask) {
+                    continue;
+                }
+            }
+
+            let start = mesh.vertices.len() as u32;
+            let face_id = facing(quad.normal);
+            for (point, uv) in quad.points.iter().zip(quad.uvs) {
+                mesh.vertices.push(PackedVertex::new(
+                    [
+                        i as f32 + point[0],
+                        j as f32 + point[1],
+                        k as f32 + point[2],
+                    ],
+                    face_id,
+                    (uv, quad.layer),
+                    3,
+                    light,
+                ));
+            }
+            mesh.indices.extend(quad_indices(start, [3; 4]));
         }
     }
 
-    pub fn gen_mesh(
-        block_data: &Array3<Block>,
-        neighbors: [Option<&Box<Array3<Block>>>; 6],
-        texture_map_info: &HashMap<u16, [[[f32; 2]; 4]; 6]>,
-    ) -> MeshData {
+    pub(super) fn gen_mesh(
+        hood: &ChunkNeighborhood,
+        texture_map_info: &HashMap<Block, FaceTextures>,
+    ) -> ChunkMeshData {
         let presize = CHUNK_SIZE.0 * CHUNK_SIZE.1 * CHUNK_SIZE.2;
-        let mut vertex_data = VertexDataList(
-            Vec::with_capacity(presize),
-            Vec::with_capacity(presize),
-            Vec::with_capacity(presize),
-        );
-        let mut indices = Vec::with_capacity(CHUNK_SIZE.0 * CHUNK_SIZE.1 * CHUNK_SIZE.2 * 3);
+        let mut meshes = ChunkMeshData {
+            opaque: MeshData::with_capacity(presize),
+            ..Default::default()
+        };
 
         for i in 0..CHUNK_SIZE.0 {
             for j in 0..CHUNK_SIZE.1 {
                 for k in 0..CHUNK_SIZE.2 {
-                    // Check if block or air
-                    if block_data.get((i, j, k)).unwrap().id != 0 {
-                        // Check adjacent blocks
-
-                        // Add right face to mesh
-                        if i == CHUNK_SIZE.0 - 1 || block_data.get((i + 1, j, k)).unwrap().id == 0 {
-                            // Check neighbor chunk if block is on edge
-                            if i == CHUNK_SIZE.0 - 1 {
-                                let neighbor = &neighbors[0];
-                                if neighbor.is_none()
-                                    || neighbor
-                                        .as_ref()
-                                        .unwrap()
-                                        .get((0, j, k))
-                                        .unwrap_or(&Block::air())
-                                        .id
-                                        == 0
-                                {
-                                    Chunk::add_face(
-                                        block_data,
-                                        &mut vertex_data,
-                                        &mut indices,
-                                        (i, j, k),
-                                        Faces::RIGHT,
-                                        texture_map_info,
-                                    );
-                                }
-                            } else {
-                                Chunk::add_face(
-                                    block_data,
-                                    &mut vertex_data,
-                                    &mut indices,
-                                    (i, j, k),
-                                    Faces::RIGHT,
-                                    texture_map_info,
-                                );
-                            }
-                        }
-
-                        // Add left face to mesh
-                        if i == 0 || block_data.get((i - 1, j, k)).unwrap().id == 0 {
-                            if i == 0 {
-                                let neighbor = &neighbors[1];
-                                if neighbor.is_none()
-                                    || neighbor
-                                        .as_ref()
-                                        .unwrap()
-                                        .get((CHUNK_SIZE.0 - 1, j, k))
-                                        .unwrap_or(&Block::air())
-                                        .id
-                                        == 0
-                                {
-                                    Chunk::add_face(
-                                        block_data,
-                                        &mut vertex_data,
-                                        &mut indices,
-                                        (i, j, k),
-                                        Faces::LEFT,
-                                        texture_map_info,
-                                    );
-                                }
-                            } else {
-                                Chunk::add_face(
-                                    block_data,
-                                    &mut vertex_data,
-                                    &mut indices,
-                                    (i, j, k),
-                                    Faces::LEFT,
-                                    texture_map_info,
-                                );
-                            }
-                        }
-
-                        // Add bottom face to mesh
-                        if j == 0 || block_data.get((i, j - 1, k)).unwrap().id == 0 {
-                            if j == 0 {
-                                let neighbor = &neighbors[2];
-                                if neighbor.is_none()
-                                    || neighbor
-                                        .as_ref()
-                                        .unwrap()
-                                        .get((i, CHUNK_SIZE.1 - 1, k))
-                                        .unwrap_or(&Block::air())
-                                        .id
-                                        == 0
-                                {
-                                    Chunk::add_face(
-                                        block_data,
-                                        &mut vertex_data,
-                                        &mut indices,
-                                        (i, j, k),
-                                        Faces::BOTTOM,
-                                        texture_map_info,
-                                    );
-                                }
-                            } else {
-                                Chunk::add_face(
-                                    block_data,
-                                    &mut vertex_data,
-                                    &mut indices,
-                                    (i, j, k),
-                                    Faces::BOTTOM,
-                                    texture_map_info,
-                                );
-                            }
-                        }
-
-                        // Add top face to mesh
-                        if j == CHUNK_SIZE.1 - 1 || block_data.get((i, j + 1, k)).unwrap().id == 0 {
-                            if j == CHUNK_SIZE.1 - 1 {
-                                let neighbor = &neighbors[3];
-                                if neighbor.is_none()
-                                    || neighbor
-                                        .as_ref()
-                                        .unwrap()
-                                        .get((i, 0, k))
-                                        .unwrap_or(&Block::air())
-                                        .id
-                                        == 0
-                                {
-                                    Chunk::add_face(
-                                        block_data,
-                                        &mut vertex_data,
-                                        &mut indices,
-                                        (i, j, k),
-                                        Faces::TOP,
-                                        texture_map_info,
-                                    );
-                                }
-                            } else {
-                                Chunk::add_face(
-                                    block_data,
-                                    &mut vertex_data,
-                                    &mut indices,
-                                    (i, j, k),
-                                    Faces::TOP,
-                                    texture_map_info,
-                                );
-                            }
-                        }
-
-                        // Add front face to mesh
-                        if k == CHUNK_SIZE.2 - 1 || block_data.get((i, j, k + 1)).unwrap().id == 0 {
-                            if k == CHUNK_SIZE.2 - 1 {
-                                let neighbor = &neighbors[4];
-                                if neighbor.is_none()
-                                    || neighbor
-                                        .as_ref()
-                                        .unwrap()
-                                        .get((i, j, 0))
-                                        .unwrap_or(&Block::air())
-                                        .id
-                                        == 0
-                                {
-                                    Chunk::add_face(
-                                        block_data,
-                                        &mut vertex_data,
-                                        &mut indices,
-                                        (i, j, k),
-                                        Faces::FRONT,
-                                        texture_map_info,
-                                    );
-                                }
-                            } else {
-                                Chunk::add_face(
-                                    block_data,
-                                    &mut vertex_data,
-                                    &mut indices,
-                                    (i, j, k),
-                                    Faces::FRONT,
-                                    texture_map_info,
-                                );
-                            }
-                        }
-
-                        // Add back face to mesh
-                        if k == 0 || block_data.get((i, j, k - 1)).unwrap().id == 0 {
-                            if k == 0 {
-                                let neighbor = &neighbors[5];
-                                if neighbor.is_none()
-                                    || neighbor
-                                        .as_ref()
-                                        .unwrap()
-                                        .get((i, j, CHUNK_SIZE.2 - 1))
-                                        .unwrap_or(&Block::air())
-                                        .id
-                                        == 0
-                                {
-                                    Chunk::add_face(
-                                        block_data,
-                                        &mut vertex_data,
-                                        &mut indices,
-                                        (i, j, k),
-                                        Faces::BACK,
-                                        texture_map_info,
-                                    );
-                                }
-                            } else {
-                                Chunk::add_face(
-                                    block_data,
-                                    &mut vertex_data,
-                                    &mut indices,
-                                    (i, j, k),
-                                    Faces::BACK,
-                                    texture_map_info,
-                                );
-                            }
+                    let pos = (i as i32, j as i32, k as i32);
+                    let Some(mesh) = meshes.get_mut(hood.properties(pos).render_type) else {
+                        continue;
+                    };
+                    if let Some(model) = hood.model(pos) {
+                        Chunk::add_model(hood, mesh, (i, j, k), model);
+                        continue;
+                    }
+                    for face in Faces::ALL {
+                        if hood.is_face_visible((i, j, k), face) {
+                            Chunk::add_face(hood, mesh, (i, j, k), face, texture_map_info);
                         }
                     }
                 }
             }
         }
 
-        (vertex_data.0, vertex_data.1, vertex_data.2, indices)
+        meshes
     }
 
     pub fn set_block(&mut self, (i, j, k): (usize, usize, usize), block: Block) -> bool {
-        let mut needs_update = false;
-
-        match self.block_data {
+        match self.block_data.as_mut() {
             None => {
-                self.block_data = Some(Box::new(ndarray::Array3::default(CHUNK_SIZE)));
-                needs_update = true;
+                let mut data = BlockStorage::default();
+                data.set((i, j, k), block);
+                self.block_data = Some(data);
+                self.modified = true;
+                true
             }
-            Some(_) => {
-                if self.block_data.as_ref().unwrap()[[i, j, k]] != block {
+            Some(data) => {
+                if data.set((i, j, k), block) != block {
                     self.needs_update = true;
-                    needs_update = true;
+                    self.modified = true;
+                    true
+                } else {
+                    false
                 }
             }
         }
-
-        self.block_data.as_mut().unwrap()[[i, j, k]] = block;
-
-        needs_update
     }
 
     pub fn get_block(&self, (i, j, k): (usize, usize, usize)) -> Option<Block> {
-        self.block_data.as_ref().map(|data| data[(i, j, k)].clone())
+        self.block_data.as_ref().map(|data| data.get((i, j, k)))
     }
 
     // pub fn get_coord(&self) -> ChunkCoord {
     //     self.coord
     // }
 
-    pub fn get_data_mut(&mut self) -> &mut Option<Box<ndarray::Array3<Block>>> {
+    pub fn get_data_mut(&mut self) -> &mut ChunkData {
         &mut self.block_data
     }
 
-    pub fn get_data(&self) -> &Option<Box<ndarray::Array3<Block>>> {
+    pub fn get_data(&self) -> &ChunkData {
         &self.block_data
     }
 
+    pub fn get_light(&self) -> &LightStorage {
+        &self.light
+    }
+
+    pub fn get_light_mut(&mut self) -> &mut LightStorage {
+        &mut self.light
+    }
+
+    pub fn get_connectivity(&self) -> ChunkConnectivity {
+        self.connectivity
+    }
+
+    /// Recomputes which faces of the chunk can see each other, after its blocks changed
+    pub fn update_connectivity(&mut self, properties: &BlockPropertyTable) {
+        self.connectivity = ChunkConnectivity::compute(&self.block_data, properties);
+    }
+
+    /// Finds the air blocks that fluid in the chunk can flow into, such as the caves under a sea
+    /// the generator filled
+    pub fn find_fluid_edges(&mut self, properties: &BlockPropertyTable) {
+        self.fluid_edges = match &self.block_data {
+            Some(data) => fluid_edges(data, properties),
+            None => Vec::new(),
+        };
+    }
+
+    /// Returns the air blocks found by `find_fluid_edges`, leaving none behind
+    pub fn take_fluid_edges(&mut self) -> Vec<(usize, usize, usize)> {
+        std::mem::take(&mut self.fluid_edges)
+    }
+
     pub fn set_updated(&mut self) {
         self.needs_update = false;
     }
@@ -429,6 +330,12 @@ impl Chunk {
     pub fn request_update(&mut self) {
         self.needs_update = true;
     }
-}
 
-struct VertexDataList(Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>);
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn set_saved(&mut self) {
+        self.modified = false;
+    }
+}
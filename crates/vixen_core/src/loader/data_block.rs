@@ -0,0 +1,246 @@
+use std::{hash::Hash, sync::Mutex};
+
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{state_count, BlockType, RenderType, StateProperty};
+
+use super::{model::ModelDefinition, pack::DataPackErrorKind};
+
+/// Properties of a block as written in the data pack, next to its textures. Blocks that are not
+/// registered by code are registered from these, while blocks registered by code keep their own.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockDefinition {
+    /// Name shown to players, the block's code name when left out
+    pub name: Option<String>,
+    pub durability: f32,
+    pub solid: bool,
+    pub render_type: RenderType,
+    /// Whether the block fills its whole cell, by default whether its model is a cube
+    pub full_cube: Option<bool>,
+    pub light_emission: u8,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<PropertyDefinition>,
+}
+
+impl Default for BlockDefinition {
+    fn default() -> Self {
+        Self {
+            name: None,
+            durability: 1.0,
+            solid: true,
+            render_type: RenderType::Opaque,
+            full_cube: None,
+            light_emission: 0,
+            properties: Vec::new(),
+        }
+    }
+}
+
+/// A state property of a block defined in the data pack
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyDefinition {
+    /// One of the properties that come with the game, like `"axis"`
+    Named(String),
+    /// A property of the pack's own, whose first value is the default
+    Custom { name: String, values: Vec<String> },
+}
+
+/// A block type registered from a data pack's `BlockDefinition`
+pub struct DataBlock {
+    code_name: String,
+    name: String,
+    durability: f32,
+    solid: bool,
+    render_type: RenderType,
+    full_cube: bool,
+    light_emission: u8,
+    properties: &'static [StateProperty],
+}
+
+impl DataBlock {
+    pub fn new(
+        code_name: &str,
+        definition: &BlockDefinition,
+        model: &ModelDefinition,
+    ) -> Result<Self, DataPackErrorKind> {
+        let mut interned = INTERNED.lock().unwrap();
+        let interned = interned.get_or_insert_with(Interned::default);
+        let properties = definition
+            .properties
+            .iter()
+            .map(|property| match property {
+                PropertyDefinition::Named(name) => named_property(name)
+                    .ok_or_else(|| DataPackErrorKind::InvalidProperty(name.clone())),
+                PropertyDefinition::Custom { name, values } if !values.is_empty() => {
+                    let values: Vec<_> = values.iter().map(|value| interned.str(value)).collect();
+                    Ok(StateProperty {
+                        name: interned.str(name),
+                        values: intern(&mut interned.values, values),
+                    })
+                }
+                PropertyDefinition::Custom { name, .. } => {
+                    Err(DataPackErrorKind::InvalidProperty(name.clone()))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        state_count(&properties).map_err(DataPackErrorKind::TooManyStates)?;
+
+        Ok(Self {
+            code_name: code_name.to_owned(),
+            name: definition
+                .name
+                .clone()
+                .unwrap_or_else(|| code_name.to_owned()),
+            durability: definition.durability,
+            solid: definition.solid,
+            render_type: definition.render_type,
+            full_cube: definition
+                .full_cube
+                .unwrap_or(*model == ModelDefinition::Cube),
+            light_emission: definition.light_emission.min(15),
+            properties: intern(&mut interned.properties, properties),
+        })
+    }
+}
+
+impl BlockType for DataBlock {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_durability(&self) -> f32 {
+        self.durability
+    }
+
+    fn get_code_name(&self) -> &str {
+        &self.code_name
+    }
+
+    fn is_solid(&self) -> bool {
+        self.solid
+    }
+
+    fn get_render_type(&self) -> RenderType {
+        self.render_type
+    }
+
+    fn is_full_cube(&self) -> bool {
+        self.full_cube
+    }
+
+    fn get_light_emission(&self) -> u8 {
+        self.light_emission
+    }
+
+    fn get_state_properties(&self) -> &'static [StateProperty] {
+        self.properties
+    }
+}
+
+fn named_property(name: &str) -> Option<StateProperty> {
+    [
+        StateProperty::AXIS,
+        StateProperty::FACING,
+        StateProperty::HALF,
+        StateProperty::OPEN,
+    ]
+    .into_iter()
+    .find(|property| property.name == name)
+}
+
+/// Properties are made of static strings, and registered blocks live as long as the game, so the
+/// strings of custom properties are leaked. They are kept here to leak each one only once, rather
+/// than every time a pack is reloaded.
+static INTERNED: Mutex<Option<Interned>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Interned {
+    strings: HashSet<&'static str>,
+    values: HashSet<&'static [&'static str]>,
+    properties: HashSet<&'static [StateProperty]>,
+}
+
+impl Interned {
+    fn str(&mut self, value: &str) -> &'static str {
+        match self.strings.get(value).copied() {
+            Some(interned) => interned,
+            None => {
+                let interned: &'static str = Box::leak(value.to_owned().into_boxed_str());
+                self.strings.insert(interned);
+                interned
+            }
+        }
+    }
+}
+
+/// Returns the leaked copy of `items`, leaking them if they were not seen before
+fn intern<T: Eq + Hash>(set: &mut HashSet<&'static [T]>, items: Vec<T>) -> &'static [T] {
+    match set.get(items.as_slice()).copied() {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static [T] = Vec::leak(items);
+            set.insert(interned);
+            interned
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game::{state_count, BlockType, RenderType, StateProperty},
+        loader::{model::ModelDefinition, pack::DataPackErrorKind},
+    };
+
+    use super::{BlockDefinition, DataBlock, PropertyDefinition};
+
+    #[test]
+    fn test_data_block_from_json() {
+        let definition: BlockDefinition = serde_json::from_str(
+            r#"{
+                "name": "Red Leaves",
+                "render_type": "cutout",
+                "properties": ["axis", { "name": "color", "values": ["red", "orange"] }]
+            }"#,
+        )
+        .unwrap();
+        let block = DataBlock::new("red_leaves", &definition, &ModelDefinition::Cross).unwrap();
+        assert_eq!(block.get_name(), "Red Leaves");
+        assert_eq!(block.get_code_name(), "red_leaves");
+        assert_eq!(block.get_durability(), 1.0);
+        assert_eq!(block.get_render_type(), RenderType::Cutout);
+        assert!(block.is_solid() && !block.is_full_cube());
+        assert_eq!(block.get_state_properties()[0], StateProperty::AXIS);
+        assert_eq!(block.get_state_properties()[1].values, ["red", "orange"]);
+        assert_eq!(state_count(block.get_state_properties()), Ok(6));
+
+        // Reloading the pack reuses the custom property instead of leaking it again
+        let reloaded = DataBlock::new("red_leaves", &definition, &ModelDefinition::Cross).unwrap();
+        assert!(std::ptr::eq(
+            block.get_state_properties(),
+            reloaded.get_state_properties()
+        ));
+
+        let definition: BlockDefinition =
+            serde_json::from_str(r#"{ "properties": ["colour"] }"#).unwrap();
+        assert!(DataBlock::new("red_leaves", &definition, &ModelDefinition::Cube).is_err());
+
+        // 2^16 states do not fit in a block
+        let definition = BlockDefinition {
+            properties: (0..16)
+                .map(|i| PropertyDefinition::Custom {
+                    name: format!("flag_{}", i),
+                    values: vec!["false".to_owned(), "true".to_owned()],
+                })
+                .collect(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            DataBlock::new("switchboard", &definition, &ModelDefinition::Cube),
+            Err(DataPackErrorKind::TooManyStates(_))
+        ));
+    }
+}
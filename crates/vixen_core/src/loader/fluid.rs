@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::IVec3, utils::HashSet};
+
+use crate::util::BlockCoord;
+
+use super::{Block, BlockPropertyTable, BlockStorage, CHUNK_SIZE};
+
+/// Level of a fluid block that is its own source. Flowing fluid is one level higher for every block
+/// it has spread from its source.
+pub const SOURCE_LEVEL: u16 = 0;
+
+/// Level of the farthest fluid spreads from its source
+pub const MAX_FLUID_LEVEL: u16 = 7;
+
+/// Most queued fluid updates processed in a frame, so that large floods spread over several frames
+/// instead of stalling one
+pub const FLUID_UPDATES_PER_TICK: usize = 256;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// Blocks whose fluid may have to flow, after they or one of their neighbors changed
+#[derive(Default)]
+pub struct FluidQueue {
+    queue: VecDeque<BlockCoord>,
+    queued: HashSet<BlockCoord>,
+}
+
+impl FluidQueue {
+    /// Queues the block at `pos` and its six neighbors after the block changed
+    pub fn block_changed(&mut self, pos: BlockCoord) {
+        self.push(pos);
+        for offset in HORIZONTAL.into_iter().chain([IVec3::Y, IVec3::NEG_Y]) {
+            self.push(pos + offset);
+        }
+    }
+
+    /// Queues the block at `pos` alone
+    pub fn push(&mut self, pos: BlockCoord) {
+        if self.queued.insert(pos) {
+            self.queue.push_back(pos);
+        }
+    }
+
+    /// Takes up to `limit` blocks from the front of the queue
+    pub fn take(&mut self, limit: usize) -> Vec<BlockCoord> {
+        let count = limit.min(self.queue.len());
+        let taken: Vec<BlockCoord> = self.queue.drain(..count).collect();
+        for pos in taken.iter() {
+            self.queued.remove(pos);
+        }
+        taken
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// Returns the air blocks of a chunk that are next to fluid in the same chunk
+pub(super) fn fluid_edges(
+    data: &BlockStorage,
+    properties: &BlockPropertyTable,
+) -> Vec<(usize, usize, usize)> {
+    if data.get_uniform().is_some() {
+        return Vec::new();
+    }
+    let size = IVec3::new(
+        CHUNK_SIZE.0 as i32,
+        CHUNK_SIZE.1 as i32,
+        CHUNK_SIZE.2 as i32,
+    );
+    let mut edges = HashSet::new();
+    for i in 0..CHUNK_SIZE.0 {
+        for j in 0..CHUNK_SIZE.1 {
+            for k in 0..CHUNK_SIZE.2 {
+                if !properties.get(data.get((i, j, k))).fluid {
+                    continue;
+                }
+                let pos = IVec3::new(i as i32, j as i32, k as i32);
+                for offset in HORIZONTAL.into_iter().chain([IVec3::Y, IVec3::NEG_Y]) {
+                    let next = pos + offset;
+                    if next.cmplt(IVec3::ZERO).any() || next.cmpge(size).any() {
+                        continue;
+                    }
+                    let next = (next.x as usize, next.y as usize, next.z as usize);
+                    if data.get(next).is_air() {
+                        edges.insert(next);
+                    }
+                }
+            }
+        }
+    }
+    edges.into_iter().collect()
+}
+
+/// Returns the block that flowing fluid turns the block at `pos` into, or None if it stays the
+/// same. `get_block` returns None for blocks that are not loaded, which fluid does not flow into.
+///
+/// Fluid falls into the air below it, and spreads sideways from blocks it cannot fall out of, one
+/// level higher with every block. Flowing fluid that is no longer fed by a lower level dries up.
+/// Sources stay until another block replaces them.
+pub fn flow(
+    pos: BlockCoord,
+    get_block: impl Fn(BlockCoord) -> Option<Block>,
+    properties: &BlockPropertyTable,
+) -> Option<Block> {
+    let is_fluid = |block: Block| properties.get(block).fluid;
+    let current = get_block(pos)?;
+    if is_fluid(current) && current.state == SOURCE_LEVEL {
+        return None;
+    }
+    if !current.is_air() && !is_fluid(current) {
+        return None;
+    }
+
+    let mut fed = None;
+    match get_block(pos + IVec3::Y) {
+        Some(above) if is_fluid(above) => fed = Some(Block::with_state(above.id, 1)),
+        _ => {
+            for offset in HORIZONTAL {
+                let Some(side) = get_block(pos + offset) else {
+                    continue;
+                };
+                if !is_fluid(side) || side.state >= MAX_FLUID_LEVEL {
+                    continue;
+                }
+                // Fluid that can fall does not spread
+                match get_block(pos + offset + IVec3::NEG_Y) {
+                    Some(below) if !below.is_air() && !is_fluid(below) => {}
+                    _ => continue,
+                }
+                let level = side.state + 1;
+                let lower = match fed {
+                    Some(block) => level < block.state,
+                    None => true,
+                };
+                if lower {
+                    fed = Some(Block::with_state(side.id, level));
+                }
+            }
+        }
+    }
+
+    let next = fed.unwrap_or_else(Block::air);
+    (next != current).then_some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::ivec3, utils::HashMap};
+
+    use super::{flow, fluid_edges, FluidQueue, MAX_FLUID_LEVEL};
+    use crate::{
+        game::{BlockType, RenderType},
+        loader::{Block, BlockPropertyTable, BlockStorage, Registry},
+        util::BlockCoord,
+    };
+
+    struct TestBlock(&'static str, bool);
+
+    impl BlockType for TestBlock {
+        fn get_name(&self) -> &str {
+            self.0
+        }
+
+        fn get_durability(&self) -> f32 {
+            1.0
+        }
+
+        fn get_code_name(&self) -> &str {
+            self.0
+        }
+
+        fn get_render_type(&self) -> RenderType {
+            RenderType::Transparent
+        }
+
+        fn is_fluid(&self) -> bool {
+            self.1
+        }
+    }
+
+    /// Runs fluid updates until nothing changes
+    fn settle(world: &mut HashMap<BlockCoord, Block>, properties: &BlockPropertyTable) {
+        let mut queue = FluidQueue::default();
+        for pos in world.keys() {
+            queue.block_changed(*pos);
+        }
+        while !queue.is_empty() {
+            for pos in queue.take(64) {
+                if let Some(block) = flow(pos, |pos| world.get(&pos).copied(), properties) {
+                    world.insert(pos, block);
+                    queue.block_changed(pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fluid_edges() {
+        let mut registry = Registry::default();
+        registry.register_block(TestBlock("air", false)).unwrap();
+        let stone = Block::new(registry.register_block(TestBlock("stone", false)).unwrap());
+        let water = Block::new(registry.register_block(TestBlock("water", true)).unwrap());
+        let properties = BlockPropertyTable::new(&registry);
+
+        // Water resting on stone can flow into the air on its other five sides
+        let mut data = BlockStorage::default();
+        data.set((5, 5, 5), water);
+        data.set((5, 4, 5), stone);
+        let mut edges = fluid_edges(&data, &properties);
+        edges.sort();
+        assert_eq!(
+            edges,
+            [(4, 5, 5), (5, 5, 4), (5, 5, 6), (5, 6, 5), (6, 5, 5)]
+        );
+
+        // Neighbors outside the chunk are left to the chunks they are in
+        let mut data = BlockStorage::default();
+        data.set((0, 0, 0), water);
+        assert_eq!(fluid_edges(&data, &properties).len(), 3);
+    }
+
+    #[test]
+    fn test_fluid_flow() {
+        let mut registry = Registry::default();
+        registry.register_block(TestBlock("air", false)).unwrap();
+        let stone = Block::new(registry.register_block(TestBlock("stone", false)).unwrap());
+        let water = registry.register_block(TestBlock("water", true)).unwrap();
+        let properties = BlockPropertyTable::new(&registry);
+
+        // A one block wide stone floor with air above it, and a pit in it
+        let mut world = HashMap::default();
+        for x in -10..=10 {
+            world.insert(ivec3(x, 0, 0), stone);
+            world.insert(ivec3(x, 1, 0), Block::air());
+            world.insert(ivec3(x, 2, 0), Block::air());
+        }
+        world.insert(ivec3(3, 0, 0), Block::air());
+        world.insert(ivec3(3, -1, 0), stone);
+        world.insert(ivec3(0, 1, 0), Block::with_state(water, 0));
+        settle(&mut world, &properties);
+
+        let level = |world: &HashMap<BlockCoord, Block>, x, y| {
+            let block = world[&ivec3(x, y, 0)];
+            (block.id == water).then_some(block.state)
+        };
+        assert_eq!(level(&world, 0, 1), Some(0));
+        assert_eq!(level(&world, -2, 1), Some(2));
+        assert_eq!(
+            level(&world, -(MAX_FLUID_LEVEL as i32), 1),
+            Some(MAX_FLUID_LEVEL)
+        );
+        assert_eq!(level(&world, -(MAX_FLUID_LEVEL as i32) - 1, 1), None);
+        // Fluid falls into the pit, and spreads no further across the floor past it
+        assert_eq!(level(&world, 3, 0), Some(1));
+        assert_eq!(level(&world, 4, 1), None);
+        assert_eq!(level(&world, 0, 2), None);
+
+        // Flowing fluid dries up once its source is gone
+        world.insert(ivec3(0, 1, 0), stone);
+        settle(&mut world, &properties);
+        assert!((-10..=10).all(|x| level(&world, x, 1).is_none()));
+        assert_eq!(level(&world, 3, 0), None);
+    }
+}
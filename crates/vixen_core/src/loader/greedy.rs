@@ -0,0 +1,225 @@
+use bevy::utils::HashMap;
+
+use super::{
+    chunk::{Face, Faces},
+    neighborhood::{quad_indices, ChunkNeighborhood},
+    texture::{FaceTexture, FaceTextures},
+    vertex::PackedVertex,
+    Block, Chunk, ChunkMeshData, MeshData, CHUNK_SIZE,
+};
+
+/// A face in the merge mask: the block with the ambient occlusion and light of each vertex. Only
+/// faces with equal keys are merged.
+type FaceKey = (Block, [u8; 4], [u8; 4]);
+
+impl Chunk {
+    /// Generates a mesh where coplanar faces of the same block are merged into larger quads.
+    ///
+    /// Merged rectangles are emitted as single quads whose texture coordinates run past 1.0 and
+    /// rely on the sampler repeating the texture.
+    ///
+    /// Faces only merge when their ambient occlusion and light are the same at all four corners,
+    /// since a merged quad cannot reproduce the shading of the faces inside it. Blocks with a model
+    /// are never merged.
+    pub(super) fn gen_mesh_greedy(
+        hood: &ChunkNeighborhood,
+        texture_map_info: &HashMap<Block, FaceTextures>,
+    ) -> ChunkMeshData {
+        let mut meshes = ChunkMeshData::default();
+        let size = [CHUNK_SIZE.0, CHUNK_SIZE.1, CHUNK_SIZE.2];
+
+        for i in 0..size[0] {
+            for j in 0..size[1] {
+                for k in 0..size[2] {
+                    let pos = (i as i32, j as i32, k as i32);
+                    if let Some(model) = hood.model(pos) {
+                        if let Some(mesh) = meshes.get_mut(hood.properties(pos).render_type) {
+                            Chunk::add_model(hood, mesh, (i, j, k), model);
+                        }
+                    }
+                }
+            }
+        }
+
+        for face in Faces::ALL {
+            let (n, u, v) = face.axes;
+            let mut mask: Vec<Option<FaceKey>> = vec![None; size[u] * size[v]];
+
+            for d in 0..size[n] {
+                // Mark every visible face in this slice
+                for b in 0..size[v] {
+                    for a in 0..size[u] {
+                        let mut pos = [0; 3];
+                        pos[n] = d;
+                        pos[u] = a;
+                        pos[v] = b;
+                        let pos = (pos[0], pos[1], pos[2]);
+
+                        let block_pos = (pos.0 as i32, pos.1 as i32, pos.2 as i32);
+                        let block = hood.get(block_pos);
+                        mask[b * size[u] + a] =
+                            if hood.model(block_pos).is_none() && hood.is_face_visible(pos, face) {
+                                Some((block, hood.face_ao(pos, face), hood.face_light(pos, face)))
+                            } else {
+                                None
+                            };
+                    }
+                }
+
+                // Grow rectangles of identical faces, first along u and then along v
+                for b in 0..size[v] {
+                    let mut a = 0;
+                    while a < size[u] {
+                        let Some(key @ (block, ao, light)) = mask[b * size[u] + a] else {
+                            a += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+                        let mut height = 1;
+                        let texture = texture_map_info.get(&block).unwrap()[face.face_id as usize];
+                        if ao.iter().all(|level| *level == ao[0])
+                            && light.iter().all(|level| *level == light[0])
+                            && tiles_along_axes(face, &texture.uvs)
+                        {
+                            while a + width < size[u] && mask[b * size[u] + a + width] == Some(key)
+                            {
+                                width += 1;
+                            }
+
+                            while b + height < size[v]
+                                && (a..a + width)
+                                    .all(|x| mask[(b + height) * size[u] + x] == Some(key))
+                            {
+                                height += 1;
+                            }
+                        }
+
+                        for y in b..b + height {
+                            for x in a..a + width {
+                                mask[y * size[u] + x] = None;
+                            }
+                        }
+
+                        let mut origin = [0; 3];
+                        origin[n] = d;
+                        origin[u] = a;
+                        origin[v] = b;
+                        let render_type = hood
+                            .properties((origin[0] as i32, origin[1] as i32, origin[2] as i32))
+                            .render_type;
+                        add_quad(
+                            meshes.get_mut(render_type).unwrap(),
+                            face,
+                            origin,
+                            (width, height),
+                            &texture,
+                            (ao, light),
+                        );
+
+                        a += width;
+                    }
+                }
+            }
+        }
+
+        meshes
+    }
+}
+
+/// Whether the texture's u coordinate runs along the face's u axis, and so v along v, which merged
+/// quads rely on. Faces of rotated blocks can have their texture turned a quarter, and are never
+/// merged.
+fn tiles_along_axes(face: &Face, tex_coords: &[[f32; 2]; 4]) -> bool {
+    let (_, u, _) = face.axes;
+    let point_u = |c: usize| [face.points[c].0, face.points[c].1, face.points[c].2][u];
+    (0..4).all(|a| {
+        (0..4).all(|b| (point_u(a) == point_u(b)) == (tex_coords[a][0] == tex_coords[b][0]))
+    })
+}
+
+/// Emits a merged `width` x `height` rectangle of a face as a single quad
+fn add_quad(
+    mesh: &mut MeshData,
+    face: &Face,
+    origin: [usize; 3],
+    (width, height): (usize, usize),
+    texture: &FaceTexture,
+    (ao, light): ([u8; 4], [u8; 4]),
+) {
+    let (_, u, v) = face.axes;
+
+    let start = mesh.vertices.len() as u32;
+    for (c, point) in face.points.iter().enumerate() {
+        let point = [point.0 as f32, point.1 as f32, point.2 as f32];
+        let mut position = [
+            origin[0] as f32 + point[0],
+            origin[1] as f32 + point[1],
+            origin[2] as f32 + point[2],
+        ];
+        position[u] = origin[u] as f32 + point[u] * width as f32;
+        position[v] = origin[v] as f32 + point[v] * height as f32;
+
+        mesh.vertices.push(PackedVertex::new(
+            position,
+            face.face_id as usize,
+            (
+                [
+                    texture.uvs[c][0] * width as f32,
+                    texture.uvs[c][1] * height as f32,
+                ],
+                texture.layer,
+            ),
+            ao[c],
+            light[c],
+        ));
+    }
+
+    mesh.indices.extend(quad_indices(start, ao));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use crate::loader::{
+        neighborhood::ChunkNeighborhood, texture::FaceTexture, Block, BlockPropertyTable,
+        BlockStorage, Chunk, LightStorage, CHUNK_SIZE,
+    };
+
+    #[test]
+    fn test_greedy_merges_flat_layer() {
+        let mut data = BlockStorage::default();
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                data.set((i, 0, k), Block::new(1));
+            }
+        }
+        let mut info = HashMap::new();
+        info.insert(
+            Block::new(1),
+            [FaceTexture {
+                layer: 0,
+                uvs: [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
+            }; 6],
+        );
+
+        let light = LightStorage::default();
+        let properties = BlockPropertyTable::default();
+        let models = HashMap::new();
+        let hood = ChunkNeighborhood::new(&data, &light, Default::default(), &properties, &models);
+        let naive = Chunk::gen_mesh(&hood, &info).opaque;
+        let greedy = Chunk::gen_mesh_greedy(&hood, &info).opaque;
+
+        assert!(greedy.vertices.len() * 8 < naive.vertices.len());
+        assert_eq!(greedy.vertices.len() / 4 * 6, greedy.indices.len());
+        // The top and bottom of the layer are single quads
+        let full = [CHUNK_SIZE.0 as f32, CHUNK_SIZE.2 as f32];
+        let full_quads = greedy
+            .vertices
+            .iter()
+            .filter(|vertex| vertex.uv() == full)
+            .count();
+        assert_eq!(full_quads, 2);
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::VecDeque;
+
+use bevy::{math::ivec3, utils::HashSet};
+
+use crate::util::{block_to_chunk_coord, block_to_chunk_local_coord, BlockCoord, ChunkCoord};
+
+use super::{
+    palette::{linear_index, VOLUME},
+    Block, BlockPropertyTable, ChunkMap, CHUNK_SIZE,
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// Neighbor offsets, in the same order as `Faces::ALL`
+const DIRECTIONS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, -1, 0),
+    (0, 1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+const DOWN: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    /// Light coming from the open sky, which travels straight down without dimming
+    Sky,
+    /// Light emitted by blocks
+    Block,
+}
+
+impl LightChannel {
+    const ALL: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+
+    #[inline]
+    fn shift(self) -> u32 {
+        match self {
+            LightChannel::Sky => 4,
+            LightChannel::Block => 0,
+        }
+    }
+}
+
+/// Sky and block light levels of a chunk, packed as two nibbles per block.
+///
+/// Chunks where every block has the same light levels keep no per-block data.
+#[derive(Clone, Debug, Default)]
+pub struct LightStorage {
+    uniform: u8,
+    data: Option<Box<[u8]>>,
+}
+
+impl LightStorage {
+    /// Light storage where every block has `level` in `channel` and no light in the other one
+    pub fn filled(channel: LightChannel, level: u8) -> Self {
+        Self {
+            uniform: level << channel.shift(),
+            data: None,
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, (i, j, k): (usize, usize, usize), channel: LightChannel) -> u8 {
+        (self.get_packed((i, j, k)) >> channel.shift()) & 0xF
+    }
+
+    /// Brightest of the sky and block light at (i, j, k)
+    #[inline]
+    pub fn get_max(&self, (i, j, k): (usize, usize, usize)) -> u8 {
+        let packed = self.get_packed((i, j, k));
+        (packed >> 4).max(packed & 0xF)
+    }
+
+    pub fn set(&mut self, (i, j, k): (usize, usize, usize), channel: LightChannel, level: u8) {
+        let mask = 0xF << channel.shift();
+        let data = match self.data.as_mut() {
+            Some(data) => data,
+            None => {
+                if (self.uniform & mask) == level << channel.shift() {
+                    return;
+                }
+                self.data
+                    .insert(vec![self.uniform; VOLUME].into_boxed_slice())
+            }
+        };
+        let value = &mut data[linear_index(i, j, k)];
+        *value = (*value & !mask) | (level << channel.shift());
+    }
+
+    /// Drops the per-block data if every block has the same light levels again
+    pub fn compact(&mut self) {
+        let Some(data) = self.data.as_ref() else {
+            return;
+        };
+        if data.iter().all(|value| *value == data[0]) {
+            self.uniform = data[0];
+            self.data = None;
+        }
+    }
+
+    #[inline]
+    fn get_packed(&self, (i, j, k): (usize, usize, usize)) -> u8 {
+        match self.data.as_ref() {
+            Some(data) => data[linear_index(i, j, k)],
+            None => self.uniform,
+        }
+    }
+}
+
+/// Computes the light of a chunk that was just added to the chunk map and spreads light between
+/// it and its loaded neighbors.
+///
+/// Chunks whose chunk above is not loaded yet are lit as if they were under the open sky. Once the
+/// chunk above loads, any sky light it blocks is removed again.
+///
+/// Returns the chunks whose light changed and need their meshes rebuilt.
+pub(super) fn light_new_chunk(
+    chunk_map: &mut ChunkMap,
+    properties: &BlockPropertyTable,
+    coord: ChunkCoord,
+) -> HashSet<ChunkCoord> {
+    let mut engine = LightEngine::new(chunk_map, properties);
+    let origin = coord
+        * ivec3(
+            CHUNK_SIZE.0 as i32,
+            CHUNK_SIZE.1 as i32,
+            CHUNK_SIZE.2 as i32,
+        );
+    let above = coord + ivec3(0, 1, 0);
+    let below = coord + ivec3(0, -1, 0);
+
+    let mut sky_seeds = VecDeque::new();
+    let mut block_seeds = VecDeque::new();
+    let mut sky_removals = VecDeque::new();
+    {
+        let chunk_map = &mut *engine.chunk_map;
+        let properties = engine.properties;
+        let blocks_light = |block| properties.get(block).is_opaque();
+        let sky_from_above = |i: usize, k: usize| match chunk_map.get(&above) {
+            Some(chunk) => {
+                chunk.get_light().get((i, 0, k), LightChannel::Sky) == MAX_LIGHT
+                    && !blocks_light(chunk.get_block((i, 0, k)).unwrap_or_else(Block::air))
+            }
+            None => true,
+        };
+        let open: Vec<bool> = (0..CHUNK_SIZE.0)
+            .flat_map(|i| (0..CHUNK_SIZE.2).map(move |k| (i, k)))
+            .map(|(i, k)| sky_from_above(i, k))
+            .collect();
+
+        let chunk = chunk_map.get_mut(&coord).unwrap();
+        let block_at = |pos| chunk.get_block(pos).unwrap_or_else(Block::air);
+
+        // Chunks open to the sky start fully lit, so that ones the sky light passes through
+        // untouched never allocate per-block light
+        let mut light = if open.iter().all(|open| *open) {
+            LightStorage::filled(LightChannel::Sky, MAX_LIGHT)
+        } else {
+            LightStorage::default()
+        };
+
+        // Sky light falls straight down each column until it hits a block
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                let mut lit = open[i * CHUNK_SIZE.2 + k];
+                for j in (0..CHUNK_SIZE.1).rev() {
+                    lit = lit && !blocks_light(block_at((i, j, k)));
+                    let level = if lit { MAX_LIGHT } else { 0 };
+                    light.set((i, j, k), LightChannel::Sky, level);
+                }
+            }
+        }
+
+        for i in 0..CHUNK_SIZE.0 {
+            for j in 0..CHUNK_SIZE.1 {
+                for k in 0..CHUNK_SIZE.2 {
+                    let pos = origin + ivec3(i as i32, j as i32, k as i32);
+                    let emission = properties
+                        .get(block_at((i, j, k)))
+                        .light_emission
+                        .min(MAX_LIGHT);
+                    if emission > 0 {
+                        light.set((i, j, k), LightChannel::Block, emission);
+                        block_seeds.push_back(pos);
+                    }
+
+                    // Only lit blocks that can spread somewhere new need to start the flood fill
+                    if light.get((i, j, k), LightChannel::Sky) == MAX_LIGHT
+                        && (on_border((i, j, k))
+                            || DIRECTIONS.iter().any(|(dx, dy, dz)| {
+                                let next = (
+                                    (i as i32 + dx) as usize,
+                                    (j as i32 + dy) as usize,
+                                    (k as i32 + dz) as usize,
+                                );
+                                light.get(next, LightChannel::Sky) < MAX_LIGHT - 1
+                                    && !blocks_light(block_at(next))
+                            }))
+                    {
+                        sky_seeds.push_back(pos);
+                    }
+                }
+            }
+        }
+        *chunk.get_light_mut() = light;
+
+        // Sky light that the chunk below received from the open sky assumption may now be blocked
+        if let Some(chunk_below) = chunk_map.get(&below) {
+            let chunk = chunk_map.get(&coord).unwrap();
+            for i in 0..CHUNK_SIZE.0 {
+                for k in 0..CHUNK_SIZE.2 {
+                    let top = (i, CHUNK_SIZE.1 - 1, k);
+                    if chunk_below.get_light().get(top, LightChannel::Sky) == MAX_LIGHT
+                        && chunk.get_light().get((i, 0, k), LightChannel::Sky) < MAX_LIGHT
+                    {
+                        sky_removals.push_back(
+                            below
+                                * ivec3(
+                                    CHUNK_SIZE.0 as i32,
+                                    CHUNK_SIZE.1 as i32,
+                                    CHUNK_SIZE.2 as i32,
+                                )
+                                + ivec3(i as i32, top.1 as i32, k as i32),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    engine.changed.insert(coord);
+
+    // Light already in the neighbors flows into the new chunk
+    for (face, (dx, dy, dz)) in DIRECTIONS.iter().enumerate() {
+        let neighbor = coord + ivec3(*dx, *dy, *dz);
+        if !engine.chunk_map.contains_key(&neighbor) {
+            continue;
+        }
+        for pos in border_of(origin, face) {
+            let outside = pos + ivec3(*dx, *dy, *dz);
+            if engine.get_light(outside, LightChannel::Sky).unwrap_or(0) > 0 {
+                sky_seeds.push_back(outside);
+            }
+            if engine.get_light(outside, LightChannel::Block).unwrap_or(0) > 0 {
+                block_seeds.push_back(outside);
+            }
+        }
+    }
+
+    let removals = sky_removals
+        .into_iter()
+        .map(|pos| {
+            engine.set_light(pos, LightChannel::Sky, 0);
+            (pos, MAX_LIGHT)
+        })
+        .collect();
+    sky_seeds.extend(engine.remove(removals, LightChannel::Sky));
+    engine.propagate(sky_seeds, LightChannel::Sky);
+    engine.propagate(block_seeds, LightChannel::Block);
+
+    engine.finish()
+}
+
+/// Updates the light around a block that changed from `old` to `new`.
+///
+/// Returns the chunks whose light changed and need their meshes rebuilt.
+pub(super) fn update_light(
+    chunk_map: &mut ChunkMap,
+    properties: &BlockPropertyTable,
+    pos: BlockCoord,
+    old: Block,
+    new: Block,
+) -> HashSet<ChunkCoord> {
+    let mut engine = LightEngine::new(chunk_map, properties);
+    if engine.blocks_light(old) == engine.blocks_light(new)
+        && engine.light_emission(old) == engine.light_emission(new)
+    {
+        return engine.changed;
+    }
+
+    for channel in LightChannel::ALL {
+        let mut removals = VecDeque::new();
+        let mut seeds = VecDeque::new();
+
+        let level = engine.get_light(pos, channel).unwrap_or(0);
+        if level > 0 {
+            engine.set_light(pos, channel, 0);
+            removals.push_back((pos, level));
+        }
+        seeds.extend(engine.remove(removals, channel));
+
+        let emission = engine.light_emission(new);
+        if channel == LightChannel::Block && emission > 0 {
+            engine.set_light(pos, channel, emission);
+            seeds.push_back(pos);
+        }
+        // Light from the surrounding blocks flows back into a block that stopped blocking it
+        if !engine.blocks_light(new) {
+            for (dx, dy, dz) in DIRECTIONS {
+                seeds.push_back(pos + ivec3(dx, dy, dz));
+            }
+        }
+
+        engine.propagate(seeds, channel);
+    }
+
+    engine.finish()
+}
+
+/// Breadth-first light propagation across every loaded chunk
+struct LightEngine<'a> {
+    chunk_map: &'a mut ChunkMap,
+    properties: &'a BlockPropertyTable,
+    changed: HashSet<ChunkCoord>,
+}
+
+impl<'a> LightEngine<'a> {
+    fn new(chunk_map: &'a mut ChunkMap, properties: &'a BlockPropertyTable) -> Self {
+        Self {
+            chunk_map,
+            properties,
+            changed: HashSet::default(),
+        }
+    }
+
+    /// Compacts the light of every chunk that changed, and returns the chunks to rebuild
+    fn finish(self) -> HashSet<ChunkCoord> {
+        for coord in self.changed.iter() {
+            if let Some(chunk) = self.chunk_map.get_mut(coord) {
+                chunk.get_light_mut().compact();
+            }
+        }
+        self.changed
+    }
+
+    /// Whether a block stops light from passing through it
+    #[inline]
+    fn blocks_light(&self, block: Block) -> bool {
+        self.properties.get(block).is_opaque()
+    }
+
+    /// Block light level emitted by a block
+    #[inline]
+    fn light_emission(&self, block: Block) -> u8 {
+        self.properties.get(block).light_emission.min(MAX_LIGHT)
+    }
+
+    /// Returns None if the block is in an unloaded chunk
+    fn get_light(&self, pos: BlockCoord, channel: LightChannel) -> Option<u8> {
+        self.chunk_map
+            .get(&block_to_chunk_coord(&pos))
+            .map(|chunk| {
+                chunk
+                    .get_light()
+                    .get(block_to_chunk_local_coord(&pos), channel)
+            })
+    }
+
+    fn set_light(&mut self, pos: BlockCoord, channel: LightChannel, level: u8) {
+        let coord = block_to_chunk_coord(&pos);
+        let local = block_to_chunk_local_coord(&pos);
+        let Some(chunk) = self.chunk_map.get_mut(&coord) else {
+            return;
+        };
+        chunk.get_light_mut().set(local, channel, level);
+
+        // Meshes sample the light one block past their border
+        self.changed.insert(coord);
+        let size = [CHUNK_SIZE.0, CHUNK_SIZE.1, CHUNK_SIZE.2];
+        let local = [local.0, local.1, local.2];
+        for axis in 0..3 {
+            let mut offset = [0; 3];
+            if local[axis] == 0 {
+                offset[axis] = -1;
+            } else if local[axis] == size[axis] - 1 {
+                offset[axis] = 1;
+            } else {
+                continue;
+            }
+            self.changed
+                .insert(coord + ivec3(offset[0], offset[1], offset[2]));
+        }
+    }
+
+    /// Returns None if the block is in an unloaded chunk
+    fn get_block(&self, pos: BlockCoord) -> Option<Block> {
+        self.chunk_map
+            .get(&block_to_chunk_coord(&pos))
+            .map(|chunk| {
+                chunk
+                    .get_block(block_to_chunk_local_coord(&pos))
+                    .unwrap_or_else(Block::air)
+            })
+    }
+
+    /// Spreads light outward from every position in `queue`
+    fn propagate(&mut self, mut queue: VecDeque<BlockCoord>, channel: LightChannel) {
+        while let Some(pos) = queue.pop_front() {
+            let Some(level) = self.get_light(pos, channel) else {
+                continue;
+            };
+            if level <= 1 {
+                continue;
+            }
+
+            for (direction, (dx, dy, dz)) in DIRECTIONS.iter().enumerate() {
+                let next = pos + ivec3(*dx, *dy, *dz);
+                match self.get_block(next) {
+                    Some(block) if !self.blocks_light(block) => (),
+                    _ => continue,
+                }
+
+                let target =
+                    if channel == LightChannel::Sky && direction == DOWN && level == MAX_LIGHT {
+                        MAX_LIGHT
+                    } else {
+                        level - 1
+                    };
+                if self.get_light(next, channel).unwrap_or(MAX_LIGHT) < target {
+                    self.set_light(next, channel, target);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// Darkens every block that was lit by the removed positions. Each entry holds a position that
+    /// was already set to zero and the level it had before.
+    ///
+    /// Returns the positions bordering the darkened area, which have to spread their light back in.
+    fn remove(
+        &mut self,
+        mut queue: VecDeque<(BlockCoord, u8)>,
+        channel: LightChannel,
+    ) -> VecDeque<BlockCoord> {
+        let mut relight = VecDeque::new();
+        while let Some((pos, level)) = queue.pop_front() {
+            for (direction, (dx, dy, dz)) in DIRECTIONS.iter().enumerate() {
+                let next = pos + ivec3(*dx, *dy, *dz);
+                let Some(next_level) = self.get_light(next, channel) else {
+                    continue;
+                };
+                if next_level == 0 {
+                    continue;
+                }
+
+                let lit_by_pos = next_level < level
+                    || (channel == LightChannel::Sky
+                        && direction == DOWN
+                        && level == MAX_LIGHT
+                        && next_level == MAX_LIGHT);
+                if lit_by_pos {
+                    self.set_light(next, channel, 0);
+                    queue.push_back((next, next_level));
+
+                    // Light sources inside the darkened area keep shining
+                    if channel == LightChannel::Block {
+                        let emission = self
+                            .get_block(next)
+                            .map_or(0, |block| self.light_emission(block));
+                        if emission > 0 {
+                            self.set_light(next, channel, emission);
+                            relight.push_back(next);
+                        }
+                    }
+                } else {
+                    relight.push_back(next);
+                }
+            }
+        }
+        relight
+    }
+}
+
+#[inline]
+fn on_border((i, j, k): (usize, usize, usize)) -> bool {
+    i == 0
+        || j == 0
+        || k == 0
+        || i == CHUNK_SIZE.0 - 1
+        || j == CHUNK_SIZE.1 - 1
+        || k == CHUNK_SIZE.2 - 1
+}
+
+/// World positions of the blocks on one face of a chunk, faces in the same order as `Faces::ALL`
+fn border_of(origin: BlockCoord, face: usize) -> impl Iterator<Item = BlockCoord> {
+    let size = [CHUNK_SIZE.0, CHUNK_SIZE.1, CHUNK_SIZE.2];
+    let axis = face / 2;
+    // Right, top and front faces are on the positive side of their axis
+    let fixed = if matches!(face, 0 | 3 | 4) {
+        size[axis] - 1
+    } else {
+        0
+    };
+    let (u, v) = match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+
+    (0..size[u]).flat_map(move |a| {
+        (0..size[v]).map(move |b| {
+            let mut local = [0; 3];
+            local[axis] = fixed as i32;
+            local[u] = a as i32;
+            local[v] = b as i32;
+            origin + ivec3(local[0], local[1], local[2])
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec3;
+
+    use super::{light_new_chunk, update_light, LightChannel, MAX_LIGHT};
+    use crate::loader::{Block, BlockPropertyTable, BlockStorage, Chunk, ChunkMap, CHUNK_SIZE};
+
+    #[test]
+    fn test_sky_light_under_roof() {
+        let properties = BlockPropertyTable::default();
+        let coord = ivec3(0, 0, 0);
+        let mut data = BlockStorage::default();
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                data.set((i, 16, k), Block::new(1));
+            }
+        }
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.insert(coord, Chunk::from_data(coord, data));
+        light_new_chunk(&mut chunk_map, &properties, coord);
+
+        let sky =
+            |chunk_map: &ChunkMap, pos| chunk_map[&coord].get_light().get(pos, LightChannel::Sky);
+        assert_eq!(sky(&chunk_map, (5, 20, 5)), MAX_LIGHT);
+        assert_eq!(sky(&chunk_map, (5, 10, 5)), 0);
+
+        // Opening a hole lets sky light fall through and spread out below the roof
+        chunk_map
+            .get_mut(&coord)
+            .unwrap()
+            .set_block((5, 16, 5), Block::air());
+        update_light(
+            &mut chunk_map,
+            &properties,
+            ivec3(5, 16, 5),
+            Block::new(1),
+            Block::air(),
+        );
+        assert_eq!(sky(&chunk_map, (5, 0, 5)), MAX_LIGHT);
+        assert_eq!(sky(&chunk_map, (7, 10, 5)), MAX_LIGHT - 2);
+
+        chunk_map
+            .get_mut(&coord)
+            .unwrap()
+            .set_block((5, 16, 5), Block::new(1));
+        update_light(
+            &mut chunk_map,
+            &properties,
+            ivec3(5, 16, 5),
+            Block::air(),
+            Block::new(1),
+        );
+        assert_eq!(sky(&chunk_map, (5, 0, 5)), 0);
+        assert_eq!(sky(&chunk_map, (7, 10, 5)), 0);
+    }
+
+    #[test]
+    fn test_open_air_keeps_uniform_light() {
+        let properties = BlockPropertyTable::default();
+        let coord = ivec3(0, 0, 0);
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.insert(coord, Chunk::empty(coord));
+        light_new_chunk(&mut chunk_map, &properties, coord);
+        assert!(chunk_map[&coord].get_light().data.is_none());
+        assert_eq!(
+            chunk_map[&coord]
+                .get_light()
+                .get((5, 5, 5), LightChannel::Sky),
+            MAX_LIGHT
+        );
+
+        // A block casts a shadow, and the light collapses again once it is gone
+        let pos = ivec3(5, 5, 5);
+        for (old, new) in [(Block::air(), Block::new(1)), (Block::new(1), Block::air())] {
+            chunk_map.get_mut(&coord).unwrap().set_block((5, 5, 5), new);
+            update_light(&mut chunk_map, &properties, pos, old, new);
+        }
+        assert!(chunk_map[&coord].get_light().data.is_none());
+    }
+
+    #[test]
+    fn test_sky_light_crosses_chunks() {
+        let properties = BlockPropertyTable::default();
+        let below = ivec3(0, -1, 0);
+        let above = ivec3(0, 0, 0);
+        let mut chunk_map = ChunkMap::default();
+
+        // Without a loaded chunk above, the lower chunk assumes open sky
+        chunk_map.insert(below, Chunk::empty(below));
+        light_new_chunk(&mut chunk_map, &properties, below);
+        assert_eq!(
+            chunk_map[&below]
+                .get_light()
+                .get((3, 0, 3), LightChannel::Sky),
+            MAX_LIGHT
+        );
+
+        // A solid chunk loading above takes that light away again
+        chunk_map.insert(
+            above,
+            Chunk::from_data(above, BlockStorage::new(Block::new(1))),
+        );
+        let changed = light_new_chunk(&mut chunk_map, &properties, above);
+        assert!(changed.contains(&below));
+        assert_eq!(
+            chunk_map[&below]
+                .get_light()
+                .get((3, 0, 3), LightChannel::Sky),
+            0
+        );
+    }
+}
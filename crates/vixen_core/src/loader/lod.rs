@@ -0,0 +1,224 @@
+use bevy::{math::ivec3, utils::HashMap};
+
+use crate::game::RenderType;
+
+use super::{
+    chunk::Faces,
+    neighborhood::{quad_indices, ChunkNeighborhood},
+    texture::FaceTextures,
+    vertex::PackedVertex,
+    Block, Chunk, ChunkCoord, ChunkMeshData, ChunkScanner, CHUNK_SIZE, MAX_LIGHT,
+};
+
+/// Level of detail a chunk mesh was built with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct MeshDetail {
+    /// Each cell of the mesh spans 2^level blocks along every axis
+    pub level: u8,
+    /// Neighbors, in the order of `Faces::ALL`, that are meshed at another level. The chunk is
+    /// meshed as if they were empty, so the faces along its border close the seam between the
+    /// two levels like a skirt.
+    pub seams: [bool; 6],
+}
+
+impl MeshDetail {
+    /// Returns the detail a chunk should be meshed with, or None if it is out of range
+    pub fn new(scanner: &ChunkScanner, coord: ChunkCoord) -> Option<MeshDetail> {
+        let level = scanner.lod_level(&coord)?;
+        let seams = Faces::ALL.map(|face| {
+            let neighbor = coord + ivec3(face.normal.0, face.normal.1, face.normal.2);
+            scanner.lod_level(&neighbor) != Some(level)
+        });
+        Some(MeshDetail { level, seams })
+    }
+}
+
+impl Chunk {
+    /// Generates a mesh where every cell of 2^level blocks along each axis is drawn as a single
+    /// block, for chunks far from the camera. Textures are stretched over the whole cell.
+    pub(super) fn gen_mesh_lod(
+        hood: &ChunkNeighborhood,
+        texture_map_info: &HashMap<Block, FaceTextures>,
+        level: u8,
+    ) -> ChunkMeshData {
+        let scale = 1 << level;
+        let size = [CHUNK_SIZE.0, CHUNK_SIZE.1, CHUNK_SIZE.2].map(|size| (size / scale) as i32);
+
+        // The chunk's cells, surrounded by a layer of cells from its face neighbors
+        let padded = size.map(|size| size as usize + 2);
+        let index = |[x, y, z]: [i32; 3]| {
+            ((x + 1) as usize * padded[1] + (y + 1) as usize) * padded[2] + (z + 1) as usize
+        };
+        let mut cells = vec![None; padded[0] * padded[1] * padded[2]];
+        for x in -1..=size[0] {
+            for y in -1..=size[1] {
+                for z in -1..=size[2] {
+                    let outside = [x, y, z]
+                        .iter()
+                        .zip(size)
+                        .filter(|(value, size)| **value < 0 || **value >= *size)
+                        .count();
+                    // Cells of diagonal neighbors are never looked at
+                    if outside <= 1 {
+                        cells[index([x, y, z])] = downsample(hood, [x, y, z], scale as i32);
+                    }
+                }
+            }
+        }
+
+        let mut meshes = ChunkMeshData::default();
+        for x in 0..size[0] {
+            for y in 0..size[1] {
+                for z in 0..size[2] {
+                    let Some(block) = cells[index([x, y, z])] else {
+                        continue;
+                    };
+                    let render_type = hood.block_properties(block).render_type;
+                    let Some(mesh) = meshes.get_mut(render_type) else {
+                        continue;
+                    };
+                    let origin = [x, y, z].map(|value| value * scale as i32);
+
+                    for face in Faces::ALL {
+                        let normal = [face.normal.0, face.normal.1, face.normal.2];
+                        let adjacent = cells[index([x + normal[0], y + normal[1], z + normal[2]])];
+                        let hidden = match adjacent {
+                            Some(adjacent) => {
+                                let properties = hood.block_properties(adjacent);
+                                properties.is_opaque()
+                                    || (properties.render_type == RenderType::Transparent
+                                        && adjacent == block)
+                            }
+                            None => false,
+                        };
+                        if hidden {
+                            continue;
+                        }
+
+                        // Light the face with the block just in front of the middle of the cell
+                        let (n, _, _) = face.axes;
+                        let mut sample = origin.map(|value| value + scale as i32 / 2);
+                        sample[n] = if normal[n] > 0 {
+                            origin[n] + scale as i32
+                        } else {
+                            origin[n] - 1
+                        };
+                        let light = hood
+                            .get_light((sample[0], sample[1], sample[2]))
+                            .unwrap_or(MAX_LIGHT)
+                            * 4;
+
+                        let texture = texture_map_info.get(&block).unwrap()[face.face_id as usize];
+                        let start = mesh.vertices.len() as u32;
+                        for (point, uv) in face.points.iter().zip(texture.uvs) {
+                            let point = [point.0, point.1, point.2];
+                            mesh.vertices.push(PackedVertex::new(
+                                [0, 1, 2].map(|i| (origin[i] + point[i] * scale as i32) as f32),
+                                face.face_id as usize,
+                                (uv, texture.layer),
+                                3,
+                                light,
+                            ));
+                        }
+                        mesh.indices.extend(quad_indices(start, [3; 4]));
+                    }
+                }
+            }
+        }
+
+        meshes
+    }
+}
+
+/// Picks the block that stands for a cell of `scale` blocks along each axis, or None if less than
+/// half of the cell is filled. Blocks with an open space above them win over buried ones, so that
+/// grass stays on top of distant hills.
+fn downsample(hood: &ChunkNeighborhood, cell: [i32; 3], scale: i32) -> Option<Block> {
+    let is_filled = |block: Block| {
+        let properties = hood.block_properties(block);
+        properties.is_visible() && properties.full_cube
+    };
+
+    // Each block with the number of times it appears, and appears on the surface
+    let mut counts: Vec<(Block, u32, u32)> = Vec::new();
+    let mut filled = 0;
+    for dx in 0..scale {
+        for dy in 0..scale {
+            for dz in 0..scale {
+                let pos = (
+                    cell[0] * scale + dx,
+                    cell[1] * scale + dy,
+                    cell[2] * scale + dz,
+                );
+                let block = hood.get(pos);
+                if !is_filled(block) {
+                    continue;
+                }
+                filled += 1;
+
+                let surface = !is_filled(hood.get((pos.0, pos.1 + 1, pos.2))) as u32;
+                match counts.iter_mut().find(|(other, _, _)| *other == block) {
+                    Some((_, count, surface_count)) => {
+                        *count += 1;
+                        *surface_count += surface;
+                    }
+                    None => counts.push((block, 1, surface)),
+                }
+            }
+        }
+    }
+
+    if filled * 2 < scale * scale * scale {
+        return None;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count, surface_count)| (*surface_count, *count))
+        .map(|(block, _, _)| block)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use crate::loader::{
+        neighborhood::ChunkNeighborhood, texture::FaceTexture, Block, BlockPropertyTable,
+        BlockStorage, Chunk, LightStorage, CHUNK_SIZE,
+    };
+
+    #[test]
+    fn test_lod_mesh_scales_cells() {
+        // A flat layer one block thick disappears at 2x, so use a layer as thick as the cells
+        let mut data = BlockStorage::default();
+        for i in 0..CHUNK_SIZE.0 {
+            for j in 0..8 {
+                for k in 0..CHUNK_SIZE.2 {
+                    data.set((i, j, k), Block::new(1));
+                }
+            }
+        }
+        let mut info = HashMap::new();
+        info.insert(
+            Block::new(1),
+            [FaceTexture {
+                layer: 0,
+                uvs: [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
+            }; 6],
+        );
+
+        let light = LightStorage::default();
+        let properties = BlockPropertyTable::default();
+        let models = HashMap::new();
+        let hood = ChunkNeighborhood::new(&data, &light, Default::default(), &properties, &models);
+        let full = Chunk::gen_mesh(&hood, &info).opaque;
+        for level in 1..=3 {
+            let lod = Chunk::gen_mesh_lod(&hood, &info, level).opaque;
+            let scale = 1 << level;
+            assert_eq!(lod.vertices.len() * scale * scale, full.vertices.len());
+            assert!(lod.vertices.iter().all(|vertex| vertex
+                .position()
+                .iter()
+                .all(|value| *value % scale as f32 == 0.0)));
+        }
+    }
+}
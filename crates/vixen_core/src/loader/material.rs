@@ -0,0 +1,78 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey, StandardMaterialFlags},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_asset::RenderAssets,
+        render_resource::{
+            AsBindGroup, AsBindGroupShaderType, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+    },
+};
+
+use super::vertex::ATTRIBUTE_PACKED_VERTEX;
+
+/// Material of chunk meshes, which decodes their packed vertices and samples the block textures.
+///
+/// Bevy's shadow pass draws every caster with its own shader, which reads plain vertex positions,
+/// and has no way for a material to replace it. Chunk meshes carry a position attribute for it next
+/// to their packed vertices, which is all this material reads.
+#[derive(AsBindGroup, Debug, Clone, TypeUuid)]
+#[uuid = "5c3f1a0e-8d2b-4f6a-9e71-2b4d8c6a1f93"]
+#[uniform(0, VoxelMaterialUniform)]
+pub struct VoxelMaterial {
+    /// Block textures, one per layer of a 2D texture array
+    #[texture(1, dimension = "2d_array")]
+    #[sampler(2)]
+    pub textures: Handle<Image>,
+    pub alpha_mode: AlphaMode,
+}
+
+/// The GPU representation of the uniform data of a `VoxelMaterial`
+#[derive(Clone, Default, ShaderType)]
+pub struct VoxelMaterialUniform {
+    /// Alpha mode as the matching `StandardMaterialFlags`, which the PBR shader functions read
+    pub flags: u32,
+    pub alpha_cutoff: f32,
+}
+
+impl AsBindGroupShaderType<VoxelMaterialUniform> for VoxelMaterial {
+    fn as_bind_group_shader_type(&self, _images: &RenderAssets<Image>) -> VoxelMaterialUniform {
+        let (flags, alpha_cutoff) = match self.alpha_mode {
+            AlphaMode::Opaque => (StandardMaterialFlags::ALPHA_MODE_OPAQUE, 0.5),
+            AlphaMode::Mask(cutoff) => (StandardMaterialFlags::ALPHA_MODE_MASK, cutoff),
+            AlphaMode::Blend => (StandardMaterialFlags::ALPHA_MODE_BLEND, 0.5),
+        };
+        VoxelMaterialUniform {
+            flags: flags.bits(),
+            alpha_cutoff,
+        }
+    }
+}
+
+impl Material for VoxelMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/voxel.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/voxel.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.vertex.buffers =
+            vec![layout.get_layout(&[ATTRIBUTE_PACKED_VERTEX.at_shader_location(0)])?];
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, ErrorKind},
+    path::Path,
+};
+
+use bevy::render::{render_resource::TextureFormat, texture::dds_format_to_texture_format};
+use ddsfile::Dds;
+use png::{ColorType, Decoder, Transformations};
+
+/// A block texture with all of its mip levels, read from a DDS or a PNG file
+pub(super) struct MipChain {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    /// Pixel data of every level, starting with the full size image
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl MipChain {
+    /// Reads a DDS file along with the mip levels stored in it
+    pub fn from_dds(path: &Path) -> io::Result<MipChain> {
+        let dds = Dds::read(&mut BufReader::new(File::open(path)?)).map_err(invalid_data)?;
+        if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+            return Err(invalid_data("volume and array textures are not supported"));
+        }
+        let format = dds_format_to_texture_format(&dds, true).map_err(invalid_data)?;
+
+        let data = dds.get_data(0).map_err(invalid_data)?;
+        let main_size = dds.get_main_texture_size().unwrap();
+        let levels = (0..dds.get_num_mipmap_levels())
+            .map(|level| {
+                let (start, end) = get_mipmap_size(main_size, level);
+                data.get(start..end)
+                    .map(|level| level.to_vec())
+                    .ok_or_else(|| invalid_data("mip levels are cut short"))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(MipChain {
+            width: dds.get_width(),
+            height: dds.get_height(),
+            format,
+            levels,
+        })
+    }
+
+    /// Reads a PNG file as sRGB and generates its mip levels down to a single pixel
+    pub fn from_png(path: &Path) -> io::Result<MipChain> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?));
+        // Palettes and 16 bit channels are turned into 8 bit color
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(invalid_data)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(invalid_data)?;
+        buffer.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            ColorType::Rgba => buffer,
+            ColorType::Rgb => expand(&buffer, 3, |rgb| [rgb[0], rgb[1], rgb[2], u8::MAX]),
+            ColorType::GrayscaleAlpha => expand(&buffer, 2, |ga| [ga[0], ga[0], ga[0], ga[1]]),
+            ColorType::Grayscale => expand(&buffer, 1, |g| [g[0], g[0], g[0], u8::MAX]),
+            ColorType::Indexed => return Err(invalid_data("palette was not expanded")),
+        };
+        Ok(MipChain {
+            width: info.width,
+            height: info.height,
+            format: TextureFormat::Rgba8UnormSrgb,
+            levels: gen_mipmaps(pixels, info.width, info.height),
+        })
+    }
+
+    /// Generates the texture drawn in place of missing ones, a magenta and black checkerboard of
+    /// four squares
+    pub fn checkerboard(width: u32, height: u32) -> MipChain {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let magenta = x * 2 / width == y * 2 / height;
+                pixels.extend(if magenta {
+                    [255, 0, 255, 255]
+                } else {
+                    [0, 0, 0, 255]
+                });
+            }
+        }
+        MipChain {
+            width,
+            height,
+            format: TextureFormat::Rgba8UnormSrgb,
+            levels: gen_mipmaps(pixels, width, height),
+        }
+    }
+}
+
+/// Builds the mip levels of an sRGB RGBA8 image by averaging every 2x2 square of the level above
+/// in linear color, until the image is a single pixel along its longest side
+fn gen_mipmaps(pixels: Vec<u8>, width: u32, height: u32) -> Vec<Vec<u8>> {
+    let to_linear: Vec<f32> = (0..=u8::MAX)
+        .map(|value| srgb_to_linear(value as f32 / 255.0))
+        .collect();
+
+    let mut levels = vec![pixels];
+    let (mut width, mut height) = (width as usize, height as usize);
+    while width > 1 || height > 1 {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let previous = levels.last().unwrap();
+        let mut level = Vec::with_capacity(next_width * next_height * 4);
+        for y in 0..next_height {
+            for x in 0..next_width {
+                // Odd sizes leave out the last row or column
+                let samples = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+                    let sx = (x * 2 + dx).min(width - 1);
+                    let sy = (y * 2 + dy).min(height - 1);
+                    (sy * width + sx) * 4
+                });
+                for channel in 0..4 {
+                    let sum: f32 = samples
+                        .iter()
+                        .map(|index| previous[index + channel])
+                        .map(|value| match channel {
+                            3 => value as f32 / 255.0,
+                            _ => to_linear[value as usize],
+                        })
+                        .sum();
+                    let average = match channel {
+                        3 => sum / 4.0,
+                        _ => linear_to_srgb(sum / 4.0),
+                    };
+                    level.push((average * 255.0).round() as u8);
+                }
+            }
+        }
+        levels.push(level);
+        (width, height) = (next_width, next_height);
+    }
+    levels
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Turns pixels of `channels` bytes each into RGBA8
+fn expand(buffer: &[u8], channels: usize, to_rgba: impl Fn(&[u8]) -> [u8; 4]) -> Vec<u8> {
+    buffer.chunks_exact(channels).flat_map(to_rgba).collect()
+}
+
+fn get_mipmap_size(main_size: u32, mipmap_level: u32) -> (usize, usize) {
+    let mut start = 0;
+    let mut size = main_size;
+    for _ in 0..mipmap_level {
+        start += size;
+        size /= 4;
+    }
+    (start as usize, (start + size) as usize)
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::gen_mipmaps;
+
+    #[test]
+    fn test_gen_mipmaps() {
+        // Half black, half white and half see-through
+        let mut pixels = Vec::new();
+        for _ in 0..4 {
+            for x in 0..4 {
+                let value = if x < 2 { 0 } else { 255 };
+                pixels.extend([value, value, value, value]);
+            }
+        }
+        let levels = gen_mipmaps(pixels, 4, 4);
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[1].len(), 2 * 2 * 4);
+        assert_eq!(levels[2].len(), 4);
+        // Colors are averaged in linear space, so mid gray is brighter than 128 in sRGB
+        assert_eq!(levels[1][0..4], [0, 0, 0, 0]);
+        assert!(levels[2][0] > 180);
+        assert!((127..=128).contains(&levels[2][3]));
+    }
+}
@@ -1,8 +1,22 @@
 mod chunk;
+mod data_block;
+mod fluid;
+mod greedy;
+mod light;
+mod lod;
+mod material;
+mod mipmap;
+mod model;
+mod neighborhood;
+mod pack;
+mod palette;
 mod plugin;
 mod registry;
+mod save;
 mod scanner;
 mod texture;
+mod vertex;
+mod visibility;
 mod worldgen;
 
 use bevy::{
@@ -12,14 +26,25 @@ use bevy::{
 };
 
 pub use chunk::*;
+pub use data_block::{BlockDefinition, DataBlock, PropertyDefinition};
+pub use fluid::{flow, FluidQueue, FLUID_UPDATES_PER_TICK, MAX_FLUID_LEVEL, SOURCE_LEVEL};
+pub use light::{LightChannel, LightStorage, MAX_LIGHT};
+pub use material::VoxelMaterial;
+pub use pack::{
+    resolve_pack, DataPackError, DataPackErrorKind, DataPackErrors, PackLayer, PackManifest,
+};
+pub use palette::BlockStorage;
 pub use plugin::*;
 pub use registry::*;
+pub use save::{LevelInfo, WorldSave};
 pub use scanner::ChunkScanner;
+pub use vertex::{PackedVertex, ATTRIBUTE_PACKED_VERTEX};
+pub use visibility::ChunkConnectivity;
 pub use worldgen::ChunkMap;
 pub use worldgen::UnfinishedChunkData;
 pub use worldgen::Worldgen;
 
-use crate::util::ChunkCoord;
+use crate::{game::RenderType, util::ChunkCoord};
 
 #[derive(Component)]
 pub struct ChunkBuildTask(pub Task<(ChunkCoord, Chunk)>);
@@ -33,8 +58,109 @@ struct NeedsMeshBuild(pub HashSet<ChunkCoord>);
 #[derive(Component)]
 struct NeedsChunkBuild(pub HashSet<ChunkCoord>);
 
-type MeshData = (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>);
-type MeshDataWithCoord = (ChunkCoord, MeshData);
+#[derive(Default)]
+pub struct MeshData {
+    pub vertices: Vec<PackedVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn with_capacity(vertices: usize) -> Self {
+        Self {
+            vertices: Vec::with_capacity(vertices),
+            indices: Vec::with_capacity(vertices * 3 / 2),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Orders the quads from farthest to nearest to `eye`, so alpha blended faces draw in the right
+    /// order when seen from around that point
+    pub fn sort_quads(&mut self, eye: Vec3) {
+        sort_quads(&self.vertices, &mut self.indices, eye);
+    }
+
+    pub fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(bevy::render::mesh::PrimitiveTopology::TriangleList);
+        let vertices: Vec<[u32; 2]> = self.vertices.iter().map(|vertex| vertex.0).collect();
+        mesh.insert_attribute(ATTRIBUTE_PACKED_VERTEX, vertices);
+        // Only read by the shadow pass, which cannot decode packed vertices
+        let positions: Vec<[f32; 3]> = self.vertices.iter().map(PackedVertex::position).collect();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_indices(Some(bevy::render::mesh::Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+/// Mesh data of a chunk, split by how each block's faces are blended
+#[derive(Default)]
+pub struct ChunkMeshData {
+    pub opaque: MeshData,
+    /// Faces with fully see-through pixels, drawn with an alpha mask
+    pub cutout: MeshData,
+    /// Faces with partially see-through pixels, drawn with alpha blending
+    pub transparent: MeshData,
+}
+
+impl ChunkMeshData {
+    /// Returns the mesh data that faces of the given render type belong to
+    pub fn get_mut(&mut self, render_type: RenderType) -> Option<&mut MeshData> {
+        match render_type {
+            RenderType::Invisible => None,
+            RenderType::Opaque => Some(&mut self.opaque),
+            RenderType::Cutout => Some(&mut self.cutout),
+            RenderType::Transparent => Some(&mut self.transparent),
+        }
+    }
+}
+
+/// Reorders the indices of a quad mesh from the farthest quad to the nearest. Every quad is six
+/// indices into its own four vertices.
+pub fn sort_quads(vertices: &[PackedVertex], indices: &mut [u32], eye: Vec3) {
+    let distance = |quad: &[u32]| {
+        let start = *quad.iter().min().unwrap() as usize;
+        let center = vertices[start..start + 4]
+            .iter()
+            .fold(Vec3::ZERO, |sum, vertex| sum + Vec3::from(*vertex))
+            / 4.0;
+        center.distance_squared(eye)
+    };
+
+    let mut quads: Vec<(f32, [u32; 6])> = indices
+        .chunks_exact(6)
+        .map(|quad| (distance(quad), quad.try_into().unwrap()))
+        .collect();
+    quads.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (sorted, (_, quad)) in indices.chunks_exact_mut(6).zip(quads) {
+        sorted.copy_from_slice(&quad);
+    }
+}
+
+type MeshDataWithCoord = (ChunkCoord, ChunkMeshData);
 
 #[derive(Resource)]
 pub struct DataPack(pub String);
+
+/// Materials shared by every chunk mesh, one for each way faces are blended
+#[derive(Resource)]
+pub struct ChunkMaterials {
+    pub opaque: Handle<VoxelMaterial>,
+    pub cutout: Handle<VoxelMaterial>,
+    pub transparent: Handle<VoxelMaterial>,
+}
+
+/// Name of the world save directory under `saves/`
+#[derive(Resource)]
+pub struct WorldName(pub String);
+
+/// Selects the algorithm used to turn chunk data into meshes
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// One quad per exposed block face
+    Naive,
+    /// Coplanar faces of the same block are merged into larger quads
+    #[default]
+    Greedy,
+}
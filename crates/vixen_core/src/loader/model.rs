@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    chunk::Faces,
+    texture::{FaceTexture, FaceTextures, Matrix},
+};
+
+/// Number of model units along each edge of a block
+const UNITS: i32 = 16;
+
+/// Shape of a block as written in the data pack
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelDefinition {
+    /// A full block, meshed together with its neighbors
+    #[default]
+    Cube,
+    /// Two quads crossing diagonally through the block, seen from both sides, like flowers and
+    /// tall grass. They use the block's front texture.
+    Cross,
+    /// Axis-aligned boxes, like slabs, stairs and fence posts. Each face of a box shows the part of
+    /// the block's texture for that face that it covers.
+    Boxes(Vec<ModelBox>),
+}
+
+/// A box spanning from one corner to the other, in sixteenths of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelBox {
+    pub from: [u8; 3],
+    pub to: [u8; 3],
+}
+
+/// Which parts of a block face are covered, as 16 rows of 16 bits along the face's (u, v) axes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceMask([u16; 16]);
+
+impl FaceMask {
+    pub const EMPTY: FaceMask = FaceMask([0; 16]);
+    pub const FULL: FaceMask = FaceMask([u16::MAX; 16]);
+
+    /// Mask of the rectangle from `from` to `to` in (u, v), in sixteenths of a block
+    fn rect(from: (i32, i32), to: (i32, i32)) -> FaceMask {
+        let mut mask = FaceMask::EMPTY;
+        let row = ((1u32 << to.0) - (1u32 << from.0)) as u16;
+        for v in from.1..to.1 {
+            mask.0[v as usize] = row;
+        }
+        mask
+    }
+
+    fn union(&self, other: &FaceMask) -> FaceMask {
+        let mut mask = *self;
+        for (row, other) in mask.0.iter_mut().zip(other.0) {
+            *row |= other;
+        }
+        mask
+    }
+
+    /// Whether every part covered by `other` is also covered by this mask
+    #[inline]
+    pub fn contains(&self, other: &FaceMask) -> bool {
+        self.0
+            .iter()
+            .zip(other.0)
+            .all(|(row, other)| row & other == other)
+    }
+}
+
+/// A quad of a block model, in block space
+#[derive(Clone, Debug)]
+pub struct ModelQuad {
+    pub points: [[f32; 3]; 4],
+    pub normal: [f32; 3],
+    pub uvs: [[f32; 2]; 4],
+    /// Layer of the block texture array
+    pub layer: u32,
+    /// Id of the block face the quad lies on, if any. Such quads are hidden when the neighbor on
+    /// that side covers them.
+    pub cull_face: Option<usize>,
+    /// Part of `cull_face` the quad covers
+    pub mask: FaceMask,
+}
+
+/// A block state's model, ready for meshing
+#[derive(Clone, Debug)]
+pub struct BlockModel {
+    pub quads: Vec<ModelQuad>,
+    /// For each block face, the parts the model covers, which hide the faces of neighbors
+    pub coverage: [FaceMask; 6],
+}
+
+/// A quad in model units, before it is rotated
+struct UnitQuad {
+    points: [[i32; 3]; 4],
+    uvs: [[f32; 2]; 4],
+    layer: u32,
+}
+
+impl BlockModel {
+    /// Builds the model of a block state from its definition, its textures and its (x, y)
+    /// rotation matrix. Returns None for cubes, which the meshers handle on their own.
+    pub fn new(
+        definition: &ModelDefinition,
+        faces: &FaceTextures,
+        rotation: &Matrix,
+    ) -> Option<BlockModel> {
+        let quads = match definition {
+            ModelDefinition::Cube => return None,
+            ModelDefinition::Cross => cross_quads(&faces[4]),
+            ModelDefinition::Boxes(boxes) => box_quads(boxes, faces),
+        };
+
+        let mut model = BlockModel {
+            quads: Vec::with_capacity(quads.len()),
+            coverage: [FaceMask::EMPTY; 6],
+        };
+        for quad in quads {
+            // Rotate around the block's center
+            let points = quad.points.map(|point| {
+                let centered = point.map(|value| value - UNITS / 2);
+                [0, 1, 2]
+                    .map(|i| (0..3).map(|j| rotation[i][j] * centered[j]).sum::<i32>() + UNITS / 2)
+            });
+
+            let (cull_face, mask) = match boundary_face(&points) {
+                Some((face, mask)) => {
+                    model.coverage[face] = model.coverage[face].union(&mask);
+                    (Some(face), mask)
+                }
+                None => (None, FaceMask::EMPTY),
+            };
+            model.quads.push(ModelQuad {
+                points: points.map(|point| point.map(|value| value as f32 / UNITS as f32)),
+                normal: normal(&points),
+                uvs: quad.uvs,
+                layer: quad.layer,
+                cull_face,
+                mask,
+            });
+        }
+        Some(model)
+    }
+}
+
+/// The two diagonal planes of a block, each as a quad facing either way
+fn cross_quads(texture: &FaceTexture) -> Vec<UnitQuad> {
+    let diagonals = [
+        [
+            [0, 0, 0],
+            [0, UNITS, 0],
+            [UNITS, UNITS, UNITS],
+            [UNITS, 0, UNITS],
+        ],
+        [
+            [UNITS, 0, 0],
+            [UNITS, UNITS, 0],
+            [0, UNITS, UNITS],
+            [0, 0, UNITS],
+        ],
+    ];
+    let mut quads = Vec::with_capacity(4);
+    for points in diagonals {
+        quads.push(UnitQuad {
+            points,
+            uvs: texture.uvs,
+            layer: texture.layer,
+        });
+        let [a, b, c, d] = points;
+        let [e, f, g, h] = texture.uvs;
+        quads.push(UnitQuad {
+            points: [d, c, b, a],
+            uvs: [h, g, f, e],
+            layer: texture.layer,
+        });
+    }
+    quads
+}
+
+/// The faces of every box, leaving out faces that lie against another box of the same model
+fn box_quads(boxes: &[ModelBox], faces: &FaceTextures) -> Vec<UnitQuad> {
+    let mut quads = Vec::with_capacity(boxes.len() * 6);
+    for (index, model_box) in boxes.iter().enumerate() {
+        let from = model_box.from.map(|value| value.min(UNITS as u8) as i32);
+        let to = model_box.to.map(|value| value.min(UNITS as u8) as i32);
+
+        for face in Faces::ALL {
+            let (n, u, v) = face.axes;
+            let positive = [face.normal.0, face.normal.1, face.normal.2][n] > 0;
+            let plane = if positive { to[n] } else { from[n] };
+
+            let hidden = boxes.iter().enumerate().any(|(other_index, other)| {
+                let other_from = other.from.map(|value| value as i32);
+                let other_to = other.to.map(|value| value as i32);
+                let touches = if positive {
+                    other_from[n] == plane
+                } else {
+                    other_to[n] == plane
+                };
+                other_index != index
+                    && touches
+                    && [u, v]
+                        .iter()
+                        .all(|&axis| other_from[axis] <= from[axis] && to[axis] <= other_to[axis])
+            });
+            if hidden || from[u] == to[u] || from[v] == to[v] {
+                continue;
+            }
+
+            let texture = &faces[face.face_id as usize];
+            let mut points = [[0; 3]; 4];
+            let mut uvs = [[0.0; 2]; 4];
+            for (c, corner) in face.points.iter().enumerate() {
+                let corner = [corner.0, corner.1, corner.2];
+                let point = [0, 1, 2].map(|axis| {
+                    if corner[axis] == 1 {
+                        to[axis]
+                    } else {
+                        from[axis]
+                    }
+                });
+                points[c] = point;
+                uvs[c] = interpolate(face.points, &texture.uvs, (u, v), point);
+            }
+            quads.push(UnitQuad {
+                points,
+                uvs,
+                layer: texture.layer,
+            });
+        }
+    }
+    quads
+}
+
+/// Texture coordinates at a point of a cube face, interpolated between the face's corners
+fn interpolate(
+    corners: &[(i32, i32, i32); 4],
+    tex_coords: &[[f32; 2]; 4],
+    (u, v): (usize, usize),
+    point: [i32; 3],
+) -> [f32; 2] {
+    let s = point[u] as f32 / UNITS as f32;
+    let t = point[v] as f32 / UNITS as f32;
+    let mut uv = [0.0; 2];
+    for (corner, tex_coord) in corners.iter().zip(tex_coords) {
+        let corner = [corner.0, corner.1, corner.2];
+        let weight_u = if corner[u] == 1 { s } else { 1.0 - s };
+        let weight_v = if corner[v] == 1 { t } else { 1.0 - t };
+        uv[0] += weight_u * weight_v * tex_coord[0];
+        uv[1] += weight_u * weight_v * tex_coord[1];
+    }
+    uv
+}
+
+/// Returns the block face a quad lies flat against, facing out of the block, and the part of the
+/// face it covers
+fn boundary_face(points: &[[i32; 3]; 4]) -> Option<(usize, FaceMask)> {
+    let normal = normal(points);
+    Faces::ALL.iter().enumerate().find_map(|(id, face)| {
+        let (n, u, v) = face.axes;
+        let face_normal = [face.normal.0, face.normal.1, face.normal.2];
+        if normal != face_normal.map(|value| value as f32) {
+            return None;
+        }
+        let plane = if face_normal[n] > 0 { UNITS } else { 0 };
+        if points.iter().any(|point| point[n] != plane) {
+            return None;
+        }
+
+        let min = |axis: usize| points.iter().map(|point| point[axis]).min().unwrap();
+        let max = |axis: usize| points.iter().map(|point| point[axis]).max().unwrap();
+        Some((id, FaceMask::rect((min(u), min(v)), (max(u), max(v)))))
+    })
+}
+
+/// Unit normal of a quad whose points wind counter-clockwise when seen from the front
+fn normal(points: &[[i32; 3]; 4]) -> [f32; 3] {
+    let a = [0, 1, 2].map(|i| (points[1][i] - points[0][i]) as f32);
+    let b = [0, 1, 2].map(|i| (points[2][i] - points[0][i]) as f32);
+    let cross = [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+    let length = cross.iter().map(|value| value * value).sum::<f32>().sqrt();
+    cross.map(|value| value / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::texture::FaceTexture;
+
+    use super::{BlockModel, FaceMask, Matrix, ModelBox, ModelDefinition};
+
+    const IDENTITY: Matrix = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+    #[test]
+    fn test_slab_coverage() {
+        let faces = [FaceTexture {
+            layer: 0,
+            uvs: [[1.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0]],
+        }; 6];
+        let slab = ModelDefinition::Boxes(vec![ModelBox {
+            from: [0, 0, 0],
+            to: [16, 8, 16],
+        }]);
+        let model = BlockModel::new(&slab, &faces, &IDENTITY).unwrap();
+
+        assert_eq!(model.quads.len(), 6);
+        // Covers the whole bottom, none of the top and half of every side
+        assert_eq!(model.coverage[2], FaceMask::FULL);
+        assert_eq!(model.coverage[3], FaceMask::EMPTY);
+        for side in [0, 1, 4, 5] {
+            assert!(!model.coverage[side].contains(&FaceMask::FULL));
+            assert!(model.coverage[side].contains(&FaceMask::rect((0, 0), (16, 8))));
+        }
+
+        // The top of the slab sits in the middle of the block
+        let top = model
+            .quads
+            .iter()
+            .find(|quad| quad.normal == [0.0, 1.0, 0.0]);
+        assert!(top.unwrap().cull_face.is_none());
+
+        // Side textures only show the bottom half of the tile
+        let side = model.quads.iter().find(|quad| quad.cull_face == Some(0));
+        assert!(side.unwrap().uvs.iter().all(|uv| uv[1] >= 0.5));
+
+        // Upside down, the slab covers the top instead
+        let flipped = [[1, 0, 0], [0, -1, 0], [0, 0, -1]];
+        let model = BlockModel::new(&slab, &faces, &flipped).unwrap();
+        assert_eq!(model.coverage[3], FaceMask::FULL);
+        assert_eq!(model.coverage[2], FaceMask::EMPTY);
+    }
+}
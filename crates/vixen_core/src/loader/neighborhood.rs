@@ -0,0 +1,281 @@
+use bevy::utils::HashMap;
+
+use crate::game::{BlockProperties, RenderType};
+
+use super::{
+    chunk::{Face, Faces},
+    model::{BlockModel, FaceMask},
+    Block, BlockPropertyTable, BlockStorage, Chunk, LightStorage, CHUNK_SIZE,
+};
+
+/// The chunks around a chunk, indexed by their offset from it plus one along each axis. The middle
+/// entry stands for the chunk itself and is never read.
+pub(super) type Neighbors<'a> = [[[Option<&'a Chunk>; 3]; 3]; 3];
+
+/// A chunk's blocks and light together with the 26 chunks around it, for lookups just past the
+/// chunk border
+#[derive(Clone, Copy)]
+pub(super) struct ChunkNeighborhood<'a> {
+    center: &'a BlockStorage,
+    light: &'a LightStorage,
+    neighbors: Neighbors<'a>,
+    properties: &'a BlockPropertyTable,
+    models: &'a HashMap<Block, BlockModel>,
+}
+
+impl<'a> ChunkNeighborhood<'a> {
+    pub fn new(
+        center: &'a BlockStorage,
+        light: &'a LightStorage,
+        neighbors: Neighbors<'a>,
+        properties: &'a BlockPropertyTable,
+        models: &'a HashMap<Block, BlockModel>,
+    ) -> Self {
+        Self {
+            center,
+            light,
+            neighbors,
+            properties,
+            models,
+        }
+    }
+
+    /// Returns the block at a position relative to the center chunk.
+    ///
+    /// Positions up to one block outside the chunk are read from the neighbor they fall in, which
+    /// is a diagonal one for edge and corner positions. Positions in missing neighbors count as
+    /// air.
+    pub fn get(&self, pos: (i32, i32, i32)) -> Block {
+        match self.locate(pos) {
+            (None, local) => self.center.get(local),
+            (Some(neighbor), local) => neighbor
+                .and_then(|chunk| chunk.get_block(local))
+                .unwrap_or_else(Block::air),
+        }
+    }
+
+    /// Returns the brightest of the sky and block light at a position relative to the center chunk,
+    /// or None if the position is in a chunk that is not available
+    pub fn get_light(&self, pos: (i32, i32, i32)) -> Option<u8> {
+        match self.locate(pos) {
+            (None, local) => Some(self.light.get_max(local)),
+            (Some(neighbor), local) => neighbor.map(|chunk| chunk.get_light().get_max(local)),
+        }
+    }
+
+    /// Returns the properties of the block at a position relative to the center chunk
+    #[inline]
+    pub fn properties(&self, pos: (i32, i32, i32)) -> BlockProperties {
+        self.properties.get(self.get(pos))
+    }
+
+    /// Returns the properties of a block
+    #[inline]
+    pub fn block_properties(&self, block: Block) -> BlockProperties {
+        self.properties.get(block)
+    }
+
+    /// Returns the model of the block at a position relative to the center chunk, or None if it is
+    /// a cube
+    #[inline]
+    pub fn model(&self, pos: (i32, i32, i32)) -> Option<&'a BlockModel> {
+        self.models.get(&self.get(pos))
+    }
+
+    /// Whether the given face of the block at (i, j, k) is drawn
+    pub fn is_face_visible(&self, (i, j, k): (usize, usize, usize), face: &Face) -> bool {
+        let block = self.get((i as i32, j as i32, k as i32));
+        self.properties.get(block).is_visible()
+            && !self.is_covered((i, j, k), block, face.face_id as usize, &FaceMask::FULL)
+    }
+
+    /// Whether the part `mask` of the given face of `block` at (i, j, k) is hidden by the block
+    /// touching that face. Opaque blocks hide what their model covers, and transparent blocks only
+    /// hide faces of the same block so that the inside of a glass wall is not drawn.
+    pub fn is_covered(
+        &self,
+        (i, j, k): (usize, usize, usize),
+        block: Block,
+        face_id: usize,
+        mask: &FaceMask,
+    ) -> bool {
+        let adjacent = self.get_adjacent((i, j, k), Faces::ALL[face_id]);
+        let covers = || match self.models.get(&adjacent) {
+            // Faces come in pairs in `Faces::ALL`, so the touching face has the other id of the
+            // pair
+            Some(model) => model.coverage[face_id ^ 1].contains(mask),
+            None => true,
+        };
+        match self.properties.get(adjacent).render_type {
+            RenderType::Invisible | RenderType::Cutout => false,
+            RenderType::Opaque => covers(),
+            RenderType::Transparent => adjacent == block && covers(),
+        }
+    }
+
+    /// Returns the block touching the given face of (i, j, k)
+    pub fn get_adjacent(&self, (i, j, k): (usize, usize, usize), face: &Face) -> Block {
+        self.get((
+            i as i32 + face.normal.0,
+            j as i32 + face.normal.1,
+            k as i32 + face.normal.2,
+        ))
+    }
+
+    /// Computes the ambient occlusion level (0 to 3) of every vertex of a block face.
+    ///
+    /// Each vertex looks at the two blocks beside it and the block diagonal to it, all in the layer
+    /// the face points into.
+    pub fn face_ao(&self, pos: (usize, usize, usize), face: &Face) -> [u8; 4] {
+        let mut ao = [0; 4];
+        for (c, [side1, side2, corner]) in vertex_samples(pos, face).into_iter().enumerate() {
+            let side1 = self.properties(side1).is_opaque() as u8;
+            let side2 = self.properties(side2).is_opaque() as u8;
+            let corner = self.properties(corner).is_opaque() as u8;
+            ao[c] = if side1 == 1 && side2 == 1 {
+                0
+            } else {
+                3 - (side1 + side2 + corner)
+            };
+        }
+        ao
+    }
+
+    /// Computes the smoothed light of every vertex of a block face, in quarter light levels.
+    ///
+    /// Each vertex averages the light of the block in front of the face with the blocks used for
+    /// its ambient occlusion, skipping blocks that are opaque or not available.
+    pub fn face_light(&self, (i, j, k): (usize, usize, usize), face: &Face) -> [u8; 4] {
+        let front = (
+            i as i32 + face.normal.0,
+            j as i32 + face.normal.1,
+            k as i32 + face.normal.2,
+        );
+
+        let mut light = [0; 4];
+        for (c, samples) in vertex_samples((i, j, k), face).into_iter().enumerate() {
+            let (sum, count) = std::iter::once(front)
+                .chain(samples)
+                .filter(|pos| !self.properties(*pos).is_opaque())
+                .filter_map(|pos| self.get_light(pos))
+                .fold((0u32, 0u32), |(sum, count), level| {
+                    (sum + level as u32, count + 1)
+                });
+            light[c] = (sum * 4 + count / 2).checked_div(count).unwrap_or(0) as u8;
+        }
+        light
+    }
+
+    /// Splits a position into the neighbor it falls in, if it is outside the center chunk, and the
+    /// position within that chunk. Neighbors that are missing are `Some(None)`.
+    fn locate(
+        &self,
+        (x, y, z): (i32, i32, i32),
+    ) -> (Option<Option<&'a Chunk>>, (usize, usize, usize)) {
+        let outside = |value: i32, size: usize| -> i32 {
+            if value < 0 {
+                -1
+            } else if value >= size as i32 {
+                1
+            } else {
+                0
+            }
+        };
+        let offset = (
+            outside(x, CHUNK_SIZE.0),
+            outside(y, CHUNK_SIZE.1),
+            outside(z, CHUNK_SIZE.2),
+        );
+        let local = (
+            x.rem_euclid(CHUNK_SIZE.0 as i32) as usize,
+            y.rem_euclid(CHUNK_SIZE.1 as i32) as usize,
+            z.rem_euclid(CHUNK_SIZE.2 as i32) as usize,
+        );
+
+        match offset {
+            (0, 0, 0) => (None, local),
+            (x, y, z) => (
+                Some(self.neighbors[(x + 1) as usize][(y + 1) as usize][(z + 1) as usize]),
+                local,
+            ),
+        }
+    }
+}
+
+/// For every vertex of a face, the two blocks beside the vertex and the block diagonal to it, all
+/// in the layer the face points into
+fn vertex_samples((i, j, k): (usize, usize, usize), face: &Face) -> [[(i32, i32, i32); 3]; 4] {
+    let (n, u, v) = face.axes;
+    let normal = [face.normal.0, face.normal.1, face.normal.2];
+    let mut front = [i as i32, j as i32, k as i32];
+    front[n] += normal[n];
+
+    let offset = |offset_u: i32, offset_v: i32| {
+        let mut pos = front;
+        pos[u] += offset_u;
+        pos[v] += offset_v;
+        (pos[0], pos[1], pos[2])
+    };
+
+    face.points.map(|point| {
+        let point = [point.0, point.1, point.2];
+        let offset_u = if point[u] == 1 { 1 } else { -1 };
+        let offset_v = if point[v] == 1 { 1 } else { -1 };
+        [
+            offset(offset_u, 0),
+            offset(0, offset_v),
+            offset(offset_u, offset_v),
+        ]
+    })
+}
+
+/// Triangle indices for a quad, split along the diagonal that keeps occlusion from bleeding across
+/// the whole face
+#[inline]
+pub(super) fn quad_indices(start: u32, ao: [u8; 4]) -> [u32; 6] {
+    if ao[0] + ao[2] < ao[1] + ao[3] {
+        [1, 2, 3, 3, 0, 1].map(|index| start + index)
+    } else {
+        [0, 1, 2, 2, 3, 0].map(|index| start + index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::ivec3, utils::HashMap};
+
+    use super::{ChunkNeighborhood, Neighbors};
+    use crate::loader::{Block, BlockPropertyTable, BlockStorage, Chunk, LightStorage, CHUNK_SIZE};
+
+    #[test]
+    fn test_diagonal_neighbors() {
+        let chunk = |(i, j, k), block| {
+            let mut data = BlockStorage::default();
+            data.set((i, j, k), block);
+            Chunk::from_data(ivec3(0, 0, 0), data)
+        };
+        let corner = chunk((0, 0, 0), Block::new(1));
+        let edge = chunk((CHUNK_SIZE.0 - 1, 5, 0), Block::new(2));
+        let mut neighbors: Neighbors = Default::default();
+        neighbors[2][2][2] = Some(&corner);
+        neighbors[0][1][2] = Some(&edge);
+
+        let center = BlockStorage::default();
+        let light = LightStorage::default();
+        let properties = BlockPropertyTable::default();
+        let models = HashMap::new();
+        let hood = ChunkNeighborhood::new(&center, &light, neighbors, &properties, &models);
+
+        let size = (
+            CHUNK_SIZE.0 as i32,
+            CHUNK_SIZE.1 as i32,
+            CHUNK_SIZE.2 as i32,
+        );
+        assert_eq!(hood.get(size), Block::new(1));
+        assert_eq!(hood.get((-1, 5, size.2)), Block::new(2));
+        assert!(hood.get_light((-1, 5, size.2)).is_some());
+        // Positions in missing neighbors are air without light
+        assert_eq!(hood.get((-1, -1, -1)), Block::air());
+        assert_eq!(hood.get_light((-1, -1, -1)), None);
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{
+    prelude::Resource,
+    render::render_resource::TextureFormat,
+    time::{Timer, TimerMode},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::game::TooManyStates;
+
+use super::{vertex::MAX_LAYERS, RegistryError};
+
+/// How often the files of the active data pack are checked for changes
+const POLL_INTERVAL: f32 = 1.0;
+
+/// Names of the data packs under `assets/packs/`, in alphabetical order
+pub(super) fn available_packs() -> Vec<String> {
+    let mut packs: Vec<String> = fs::read_dir("assets/packs")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    packs.sort();
+    packs
+}
+
+/// Metadata of a data pack, read from the `pack.json` at its root
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PackManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// Packs that blocks and textures missing from this one are looked up in, highest priority
+    /// first. Each parent brings its own parents along, right after itself.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<String>,
+}
+
+/// One of the packs a data pack is built from
+pub struct PackLayer {
+    pub dir: PathBuf,
+    pub manifest: PackManifest,
+}
+
+/// Returns `data_pack` followed by the packs it inherits from, highest priority first. A pack
+/// reached through several parents is only used at its highest priority. Packs that are missing
+/// or that inherit from themselves are reported in `errors` and left out.
+pub fn resolve_pack(data_pack: &str, errors: &mut Vec<DataPackError>) -> Vec<PackLayer> {
+    let mut layers = Vec::new();
+    add_layers(
+        data_pack,
+        Path::new("assets/packs"),
+        &mut Vec::new(),
+        &mut layers,
+        errors,
+    );
+    layers
+}
+
+fn add_layers(
+    name: &str,
+    referrer: &Path,
+    path: &mut Vec<String>,
+    layers: &mut Vec<PackLayer>,
+    errors: &mut Vec<DataPackError>,
+) {
+    if path.iter().any(|pack| pack == name) {
+        let error = DataPackErrorKind::ParentCycle(name.to_owned());
+        errors.push(DataPackError::new(referrer, error));
+        return;
+    }
+    let dir = PathBuf::from(format!("assets/packs/{}", name));
+    if layers.iter().any(|layer| layer.dir == dir) {
+        return;
+    }
+    if !dir.is_dir() {
+        let error = DataPackErrorKind::UnknownPack(name.to_owned());
+        errors.push(DataPackError::new(referrer, error));
+        return;
+    }
+
+    let manifest_path = dir.join("pack.json");
+    let manifest = match load_manifest(&manifest_path) {
+        Ok(Some(manifest)) => manifest,
+        // Packs without a manifest stand on their own
+        Ok(None) => PackManifest {
+            name: name.to_owned(),
+            ..Default::default()
+        },
+        Err(e) => {
+            errors.push(e);
+            return;
+        }
+    };
+    let parents = manifest.parents.clone();
+    layers.push(PackLayer { dir, manifest });
+
+    path.push(name.to_owned());
+    for parent in parents.iter() {
+        add_layers(parent, &manifest_path, path, layers, errors);
+    }
+    path.pop();
+}
+
+fn load_manifest(path: &Path) -> Result<Option<PackManifest>, DataPackError> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(DataPackError::new(path, DataPackErrorKind::Io(e))),
+    };
+    serde_json::from_reader(io::BufReader::new(file))
+        .map(Some)
+        .map_err(|e| DataPackError::new(path, DataPackErrorKind::Json(e)))
+}
+
+/// A problem found while loading a data pack. Loading carries on past it, and whatever it affects
+/// is drawn with the missing texture.
+#[derive(Debug)]
+pub struct DataPackError {
+    /// File the problem is in
+    pub path: PathBuf,
+    /// Block whose definition has the problem
+    pub block: Option<String>,
+    pub kind: DataPackErrorKind,
+}
+
+#[derive(Debug)]
+pub enum DataPackErrorKind {
+    /// The file or directory could not be read or decoded
+    Io(io::Error),
+    /// `blocks.json` or `pack.json` is not valid
+    Json(serde_json::Error),
+    /// A data pack or one of its parents does not exist
+    UnknownPack(String),
+    /// A pack is its own parent, directly or through other packs
+    ParentCycle(String),
+    /// A texture does not have the size and format of the pack's first texture
+    TextureMismatch {
+        size: (u32, u32, TextureFormat),
+        expected: (u32, u32, TextureFormat),
+    },
+    /// The pack has more textures than vertices can address
+    TooManyTextures,
+    /// A block defined in `blocks.json` has a state property that is neither one of the game's nor
+    /// lists its values
+    InvalidProperty(String),
+    /// A block has more states than a block can hold
+    TooManyStates(TooManyStates),
+    /// A block uses a texture the pack does not have
+    UnknownTexture(String),
+    /// A registered block is missing from `blocks.json`
+    MissingBlock,
+    /// A block defined in `blocks.json` could not be registered, such as a block added to the pack
+    /// while the world is open
+    Registry(RegistryError),
+}
+
+impl DataPackError {
+    pub fn new(path: impl Into<PathBuf>, kind: DataPackErrorKind) -> Self {
+        Self {
+            path: path.into(),
+            block: None,
+            kind,
+        }
+    }
+
+    pub fn with_block(mut self, block: &str) -> Self {
+        self.block = Some(block.to_owned());
+        self
+    }
+}
+
+impl fmt::Display for DataPackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+        if let Some(block) = &self.block {
+            write!(f, "{}: ", block)?;
+        }
+        match &self.kind {
+            DataPackErrorKind::Io(e) => write!(f, "{}", e),
+            DataPackErrorKind::Json(e) => write!(f, "{}", e),
+            DataPackErrorKind::UnknownPack(name) => write!(f, "there is no data pack {}", name),
+            DataPackErrorKind::ParentCycle(name) => {
+                write!(f, "data pack {} inherits from itself", name)
+            }
+            DataPackErrorKind::TextureMismatch { size, expected } => write!(
+                f,
+                "texture is {}x{} {:?}, but the pack's textures are {}x{} {:?}",
+                size.0, size.1, size.2, expected.0, expected.1, expected.2
+            ),
+            DataPackErrorKind::TooManyTextures => {
+                write!(f, "data packs can have at most {} textures", MAX_LAYERS - 1)
+            }
+            DataPackErrorKind::InvalidProperty(name) => {
+                write!(f, "invalid state property {}", name)
+            }
+            DataPackErrorKind::TooManyStates(e) => write!(f, "{}", e),
+            DataPackErrorKind::UnknownTexture(name) => write!(f, "unknown texture {}", name),
+            DataPackErrorKind::MissingBlock => write!(f, "block has no textures"),
+            DataPackErrorKind::Registry(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for DataPackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            DataPackErrorKind::Io(e) => Some(e),
+            DataPackErrorKind::Json(e) => Some(e),
+            DataPackErrorKind::TooManyStates(e) => Some(e),
+            DataPackErrorKind::Registry(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Problems found the last time the active data pack was loaded
+#[derive(Resource, Default)]
+pub struct DataPackErrors(pub Vec<DataPackError>);
+
+/// Watches the files of the active data pack and the packs it inherits from, which are read
+/// directly instead of through the asset server and so are not hot-reloaded by it.
+///
+/// The pack directory is polled rather than subscribed to, so an editor saving a file in several
+/// steps causes at most one reload per poll.
+#[derive(Resource)]
+pub(super) struct PackWatcher {
+    data_pack: String,
+    modified: HashMap<PathBuf, SystemTime>,
+    timer: Timer,
+}
+
+impl PackWatcher {
+    pub fn new(data_pack: &str) -> Self {
+        Self {
+            data_pack: data_pack.to_owned(),
+            modified: modified_times(data_pack),
+            timer: Timer::from_seconds(POLL_INTERVAL, TimerMode::Repeating),
+        }
+    }
+
+    /// Returns true if `data_pack` is not the pack being watched, or if a file of the pack was
+    /// added, removed or changed since the last poll
+    pub fn poll(&mut self, data_pack: &str, delta: Duration) -> bool {
+        if data_pack != self.data_pack {
+            *self = PackWatcher::new(data_pack);
+            return true;
+        }
+        if !self.timer.tick(delta).just_finished() {
+            return false;
+        }
+
+        let modified = modified_times(data_pack);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+/// Last modification time of every file in a data pack and its parents
+fn modified_times(data_pack: &str) -> HashMap<PathBuf, SystemTime> {
+    let mut times = HashMap::new();
+    for layer in resolve_pack(data_pack, &mut Vec::new()) {
+        collect_modified_times(&layer.dir, &mut times);
+    }
+    times
+}
+
+fn collect_modified_times(dir: &Path, times: &mut HashMap<PathBuf, SystemTime>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_modified_times(&entry.path(), times);
+        } else if let Ok(modified) = metadata.modified() {
+            times.insert(entry.path(), modified);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use super::{Block, CHUNK_SIZE};
+
+pub(super) const VOLUME: usize = CHUNK_SIZE.0 * CHUNK_SIZE.1 * CHUNK_SIZE.2;
+
+/// Palette-compressed block container for a single chunk.
+///
+/// Blocks are stored as indices into a per-chunk palette, bit-packed into `u64` words. A chunk made
+/// of a single block type keeps no index data at all.
+#[derive(Clone, Debug)]
+pub struct BlockStorage {
+    palette: Vec<Block>,
+    /// Number of blocks using each palette entry, entries with a count of zero are free for reuse
+    counts: Vec<u32>,
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl BlockStorage {
+    /// Create storage where every block is `block`
+    pub fn new(block: Block) -> BlockStorage {
+        BlockStorage {
+            palette: vec![block],
+            counts: vec![VOLUME as u32],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, (i, j, k): (usize, usize, usize)) -> Block {
+        self.palette[self.get_entry(linear_index(i, j, k))]
+    }
+
+    /// Sets the block at (i, j, k) and returns the block that was there before
+    pub fn set(&mut self, (i, j, k): (usize, usize, usize), block: Block) -> Block {
+        let index = linear_index(i, j, k);
+        let old_entry = self.get_entry(index);
+        let old = self.palette[old_entry];
+        if old == block {
+            return old;
+        }
+
+        let entry = self.palette_entry(block);
+        self.set_entry(index, entry);
+        self.counts[old_entry] -= 1;
+        self.counts[entry] += 1;
+
+        // Collapse back to the single-value representation once the chunk is uniform again
+        if self.counts[entry] as usize == VOLUME {
+            *self = BlockStorage::new(block);
+        }
+
+        old
+    }
+
+    /// Returns the block filling the whole chunk, if the chunk is uniform
+    pub fn get_uniform(&self) -> Option<Block> {
+        if self.bits == 0 {
+            Some(self.palette[0])
+        } else {
+            None
+        }
+    }
+
+    /// Replaces every block with `map(block)`. Only the palette is visited, unless blocks that
+    /// were different end up the same, in which case their entries are merged.
+    pub fn map_blocks(&mut self, map: impl Fn(Block) -> Block) {
+        for block in self.palette.iter_mut() {
+            *block = map(*block);
+        }
+
+        // Each entry is merged into the first entry holding the same block
+        let first: Vec<usize> = self
+            .palette
+            .iter()
+            .map(|block| self.palette.iter().position(|b| b == block).unwrap())
+            .collect();
+        if first
+            .iter()
+            .enumerate()
+            .all(|(entry, first)| entry == *first)
+        {
+            return;
+        }
+
+        for index in 0..VOLUME {
+            let entry = self.get_entry(index);
+            if first[entry] != entry {
+                self.set_entry(index, first[entry]);
+            }
+        }
+        for (entry, first) in first.into_iter().enumerate() {
+            if first != entry {
+                self.counts[first] += self.counts[entry];
+                self.counts[entry] = 0;
+            }
+        }
+        if let Some(entry) = self
+            .counts
+            .iter()
+            .position(|count| *count as usize == VOLUME)
+        {
+            *self = BlockStorage::new(self.palette[entry]);
+        }
+    }
+
+    pub fn bits_per_block(&self) -> u32 {
+        self.bits
+    }
+
+    /// Approximate heap memory used by this container in bytes
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<Block>()
+            + self.counts.capacity() * std::mem::size_of::<u32>()
+            + self.data.capacity() * std::mem::size_of::<u64>()
+    }
+
+    /// Serializes the palette and packed indices
+    pub fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.palette.len() as u16).to_le_bytes());
+        for block in self.palette.iter() {
+            out.extend_from_slice(&block.id.to_le_bytes());
+            out.extend_from_slice(&block.state.to_le_bytes());
+        }
+        out.push(self.bits as u8);
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        for word in self.data.iter() {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    /// Reads storage written by `write_bytes`, returning None if the data is malformed
+    pub fn read_bytes(bytes: &[u8]) -> Option<BlockStorage> {
+        Self::read(bytes, true)
+    }
+
+    /// Reads storage written before blocks had states, where the palette only holds block ids
+    pub fn read_bytes_without_states(bytes: &[u8]) -> Option<BlockStorage> {
+        Self::read(bytes, false)
+    }
+
+    fn read(mut bytes: &[u8], with_states: bool) -> Option<BlockStorage> {
+        let palette_len = u16::from_le_bytes(take(&mut bytes)?) as usize;
+        let palette = (0..palette_len)
+            .map(|_| {
+                let id = u16::from_le_bytes(take(&mut bytes)?);
+                let state = if with_states {
+                    u16::from_le_bytes(take(&mut bytes)?)
+                } else {
+                    0
+                };
+                Some(Block::with_state(id, state))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let [bits] = take(&mut bytes)?;
+        let bits = bits as u32;
+        let words = u32::from_le_bytes(take(&mut bytes)?) as usize;
+
+        let expected_words = if bits == 0 {
+            0
+        } else {
+            VOLUME.div_ceil(64 / bits as usize)
+        };
+        if palette.is_empty() || bits > 16 || words != expected_words || palette_len > 1 << bits {
+            return None;
+        }
+
+        let data = (0..words)
+            .map(|_| take(&mut bytes).map(u64::from_le_bytes))
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut storage = BlockStorage {
+            counts: vec![0; palette.len()],
+            palette,
+            bits,
+            data,
+        };
+        for index in 0..VOLUME {
+            let entry = storage.get_entry(index);
+            *storage.counts.get_mut(entry)? += 1;
+        }
+        Some(storage)
+    }
+
+    fn palette_entry(&mut self, block: Block) -> usize {
+        if let Some(entry) = self.palette.iter().position(|b| *b == block) {
+            return entry;
+        }
+
+        if let Some(entry) = self.counts.iter().position(|count| *count == 0) {
+            self.palette[entry] = block;
+            return entry;
+        }
+
+        self.palette.push(block);
+        self.counts.push(0);
+        let bits = bits_for(self.palette.len());
+        if bits > self.bits {
+            self.repack(bits);
+        }
+        self.palette.len() - 1
+    }
+
+    #[inline]
+    fn get_entry(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) * self.bits as usize;
+        ((self.data[index / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    #[inline]
+    fn set_entry(&mut self, index: usize, entry: usize) {
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) * self.bits as usize;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | ((entry as u64) << shift);
+    }
+
+    fn repack(&mut self, bits: u32) {
+        let per_word = 64 / bits as usize;
+        let mut data = vec![0u64; VOLUME.div_ceil(per_word)];
+        for index in 0..VOLUME {
+            let entry = self.get_entry(index) as u64;
+            data[index / per_word] |= entry << ((index % per_word) * bits as usize);
+        }
+        self.bits = bits;
+        self.data = data;
+    }
+}
+
+impl Default for BlockStorage {
+    fn default() -> Self {
+        BlockStorage::new(Block::air())
+    }
+}
+
+#[inline]
+pub(super) fn linear_index(i: usize, j: usize, k: usize) -> usize {
+    (i * CHUNK_SIZE.1 + j) * CHUNK_SIZE.2 + k
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (head, tail) = bytes.split_at(N);
+    *bytes = tail;
+    head.try_into().ok()
+}
+
+/// Number of bits needed to index a palette of `len` entries
+fn bits_for(len: usize) -> u32 {
+    usize::BITS - (len - 1).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockStorage, VOLUME};
+    use crate::loader::{Block, CHUNK_SIZE};
+
+    #[test]
+    fn test_palette_growth() {
+        let mut storage = BlockStorage::default();
+        assert_eq!(storage.bits_per_block(), 0);
+
+        for id in 1..=20 {
+            storage.set((id as usize, 0, 0), Block::new(id));
+        }
+        assert_eq!(storage.bits_per_block(), 5);
+
+        for id in 1..=20 {
+            assert_eq!(storage.get((id as usize, 0, 0)), Block::new(id));
+        }
+        assert_eq!(storage.get((0, 0, 0)), Block::air());
+        assert_eq!(storage.get((31, 31, 31)), Block::air());
+    }
+
+    #[test]
+    fn test_uniform_collapse() {
+        let mut storage = BlockStorage::default();
+        storage.set((1, 2, 3), Block::new(1));
+        assert_eq!(storage.get_uniform(), None);
+
+        storage.set((1, 2, 3), Block::air());
+        assert_eq!(storage.get_uniform(), Some(Block::air()));
+
+        for i in 0..CHUNK_SIZE.0 {
+            for j in 0..CHUNK_SIZE.1 {
+                for k in 0..CHUNK_SIZE.2 {
+                    storage.set((i, j, k), Block::new(2));
+                }
+            }
+        }
+        assert_eq!(storage.get_uniform(), Some(Block::new(2)));
+        assert!(storage.heap_size() < VOLUME);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut storage = BlockStorage::default();
+        for i in 0..CHUNK_SIZE.0 {
+            storage.set((i, i, 5), Block::with_state(i as u16 % 3, i as u16 % 2));
+        }
+
+        let mut bytes = Vec::new();
+        storage.write_bytes(&mut bytes);
+        let read = BlockStorage::read_bytes(&bytes).expect("Storage should round trip");
+        for i in 0..CHUNK_SIZE.0 {
+            assert_eq!(
+                read.get((i, i, 5)),
+                Block::with_state(i as u16 % 3, i as u16 % 2)
+            );
+        }
+
+        assert!(BlockStorage::read_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_map_blocks() {
+        let mut storage = BlockStorage::default();
+        storage.set((0, 0, 0), Block::with_state(1, 3));
+        storage.set((1, 0, 0), Block::new(2));
+        storage.set((2, 0, 0), Block::new(3));
+
+        storage.map_blocks(|block| Block::with_state(block.id + 10, block.state));
+        assert_eq!(storage.get((0, 0, 0)), Block::with_state(11, 3));
+        assert_eq!(storage.get((1, 0, 0)), Block::new(12));
+        assert_eq!(storage.get((3, 0, 0)), Block::new(10));
+
+        // Blocks mapped to the same block share an entry, and collapse once they fill the chunk
+        storage.map_blocks(|block| {
+            if block.id == 13 {
+                Block::new(12)
+            } else {
+                block
+            }
+        });
+        assert_eq!(storage.get((2, 0, 0)), Block::new(12));
+        storage.set((1, 0, 0), Block::new(10));
+        storage.set((2, 0, 0), Block::new(10));
+        storage.map_blocks(|block| {
+            if block.id == 11 {
+                Block::new(10)
+            } else {
+                block
+            }
+        });
+        assert_eq!(storage.get_uniform(), Some(Block::new(10)));
+    }
+}
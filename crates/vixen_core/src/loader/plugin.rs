@@ -1,22 +1,31 @@
-use bevy::render::texture::ImageSampler::Descriptor;
+use std::sync::Arc;
+
 use bevy::{
-    ecs::event::Events,
+    app::StartupStage,
     math::ivec3,
     prelude::*,
-    render::{render_resource::FilterMode, texture::ImageSampler},
+    render::{
+        render_resource::{AddressMode, FilterMode},
+        texture::ImageSampler,
+    },
 };
 use bevy_atmosphere::prelude::AtmospherePlugin;
 use futures_lite::future;
 
 use crate::{
     player::{Gravity, Player},
+    ui::MenuFont,
+    util::to_chunk_coord,
     GameState,
 };
 
-use super::DataPack;
 use super::{
+    pack::{available_packs, DataPackError, DataPackErrors, PackWatcher},
     texture::{create_texture_map, TextureMapHandle, TextureMapInfo},
-    ChunkBuildTask, ChunkScanner, Worldgen,
+    ChunkBuildTask, ChunkScanner, Registry, Worldgen, FLUID_UPDATES_PER_TICK,
+};
+use super::{
+    ChunkMaterials, DataPack, LevelInfo, MeshingMode, VoxelMaterial, WorldName, WorldSave,
 };
 
 pub struct WorldLoaderPlugin;
@@ -24,19 +33,34 @@ pub struct WorldLoaderPlugin;
 impl Plugin for WorldLoaderPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(AtmospherePlugin);
+        app.add_plugin(MaterialPlugin::<VoxelMaterial>::default());
+        app.init_resource::<MeshingMode>();
+        app.init_resource::<Registry>();
+
+        // Plugins register their blocks and biomes in startup systems
+        app.add_startup_system_to_stage(StartupStage::PostStartup, freeze_registry);
 
         app.add_system_set(
             SystemSet::on_enter(GameState::Game)
                 .label("Setup")
                 .with_system(setup),
         );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Game)
+                .with_system(save_world)
+                .with_system(hide_data_pack_errors),
+        );
         app.add_system_set(
             SystemSet::on_update(GameState::Game)
                 .label("Update")
                 .with_system(scan_chunks)
                 .with_system(queue_mesh_rebuild)
                 .with_system(build_chunks)
-                .with_system(build_meshes),
+                .with_system(build_meshes)
+                .with_system(sort_transparent_faces)
+                .with_system(update_chunk_visibility)
+                .with_system(update_fluids)
+                .with_system(switch_data_pack),
         );
         app.add_system_set(
             SystemSet::on_update(GameState::Game)
@@ -49,17 +73,67 @@ impl Plugin for WorldLoaderPlugin {
             SystemSet::on_update(GameState::Game)
                 .label("PostUpdate")
                 .after("Update")
-                .with_system(when_texture_loads)
+                .with_system(reload_data_pack)
+                .with_system(show_data_pack_errors)
                 .with_system(start_gravity),
         );
     }
 }
 
-fn setup(mut commands: Commands, mut textures: ResMut<Assets<Image>>, data_pack: Res<DataPack>) {
-    commands.insert_resource(Worldgen::new(0));
+fn freeze_registry(mut registry: ResMut<Registry>) {
+    registry.freeze();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut textures: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<VoxelMaterial>>,
+    data_pack: Res<DataPack>,
+    world_name: Res<WorldName>,
+    registry: Res<Registry>,
+) {
+    let save = WorldSave::open(&world_name.0).unwrap_or_else(|e| {
+        error!(
+            "Failed to open world save {}, playing without saving: {}",
+            world_name.0, e
+        );
+        WorldSave::in_memory()
+    });
+    let level = save.load_level().unwrap_or_else(|| LevelInfo {
+        seed: rand::random(),
+        data_pack: data_pack.0.clone(),
+        terrain: Default::default(),
+    });
+    if let Err(e) = save.save_level(&level) {
+        error!("Failed to save level.json: {}", e);
+    }
 
-    let (texture_map, texture_map_info) = create_texture_map(&data_pack.0);
-    let texture_handle: Handle<Image> = textures.add(texture_map);
+    // A world keeps the data pack it was created with, and the blocks the pack defines
+    let mut registry = registry.for_world();
+    let (texture_map, texture_map_info, errors) =
+        create_texture_map(&level.data_pack, &mut registry);
+    registry.freeze();
+    commands.insert_resource(Worldgen::new(
+        level.seed,
+        level.terrain.clone(),
+        Arc::new(save),
+        Arc::new(registry),
+    ));
+    commands.insert_resource(report_errors(&level.data_pack, errors));
+    commands.insert_resource(PackWatcher::new(&level.data_pack));
+    commands.insert_resource(DataPack(level.data_pack));
+    let texture_handle = add_texture_map(&mut textures, texture_map);
+    let mut chunk_material = |alpha_mode| {
+        materials.add(VoxelMaterial {
+            textures: texture_handle.clone(),
+            alpha_mode,
+        })
+    };
+    commands.insert_resource(ChunkMaterials {
+        opaque: chunk_material(AlphaMode::Opaque),
+        cutout: chunk_material(AlphaMode::Mask(0.5)),
+        transparent: chunk_material(AlphaMode::Blend),
+    });
     commands.insert_resource(TextureMapHandle(texture_handle));
     commands.insert_resource(texture_map_info);
 
@@ -92,6 +166,10 @@ fn setup(mut commands: Commands, mut textures: ResMut<Assets<Image>>, data_pack:
     });
 }
 
+fn save_world(mut worldgen: ResMut<Worldgen>) {
+    worldgen.save_all();
+}
+
 fn scan_chunks(
     scanner: Query<&mut ChunkScanner>,
     mut worldgen: ResMut<Worldgen>,
@@ -120,22 +198,56 @@ fn queue_mesh_rebuild(mut worldgen: ResMut<Worldgen>, scanner: Query<&ChunkScann
 fn build_meshes(
     scanner: Query<&ChunkScanner>,
     meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<StandardMaterial>>,
     commands: Commands,
-    texture_map: Res<TextureMapHandle>,
+    materials: Res<ChunkMaterials>,
     texture_map_info: Res<TextureMapInfo>,
+    meshing_mode: Res<MeshingMode>,
     mut worldgen: ResMut<Worldgen>,
 ) {
     worldgen.build_meshes(
         scanner,
         meshes,
-        materials,
         commands,
-        texture_map,
+        materials,
         texture_map_info,
+        meshing_mode,
     );
 }
 
+fn sort_transparent_faces(
+    camera_transform: Query<&Transform, With<Camera3d>>,
+    meshes: ResMut<Assets<Mesh>>,
+    worldgen: Res<Worldgen>,
+    mut last_block: Local<Option<IVec3>>,
+) {
+    let eye = camera_transform.single().translation;
+    // Faces only need re-sorting once the camera moves to another block
+    let block = eye.floor().as_ivec3();
+    if *last_block != Some(block) {
+        *last_block = Some(block);
+        worldgen.sort_transparent_faces(eye, meshes);
+    }
+}
+
+fn update_chunk_visibility(
+    camera_transform: Query<&Transform, With<Camera3d>>,
+    scanner: Query<&ChunkScanner>,
+    visibilities: Query<&mut Visibility>,
+    mut worldgen: ResMut<Worldgen>,
+    mut last_chunk: Local<Option<IVec3>>,
+) {
+    // The visible chunks only change when the camera enters another chunk or chunks change
+    let chunk = to_chunk_coord(&camera_transform.single().translation);
+    if *last_chunk != Some(chunk) || worldgen.needs_visibility_update() {
+        *last_chunk = Some(chunk);
+        worldgen.update_visibility(chunk, scanner, visibilities);
+    }
+}
+
+fn update_fluids(mut worldgen: ResMut<Worldgen>) {
+    worldgen.update_fluids(FLUID_UPDATES_PER_TICK);
+}
+
 fn unload_chunks(mut worldgen: ResMut<Worldgen>, scanner: Query<&ChunkScanner>) {
     worldgen.unload_chunks(scanner);
 }
@@ -143,9 +255,10 @@ fn unload_chunks(mut worldgen: ResMut<Worldgen>, scanner: Query<&ChunkScanner>)
 fn unload_meshes(
     scanner: Query<&ChunkScanner>,
     meshes: ResMut<Assets<Mesh>>,
+    commands: Commands,
     mut worldgen: ResMut<Worldgen>,
 ) {
-    worldgen.unload_meshes(scanner, meshes);
+    worldgen.unload_meshes(scanner, meshes, commands);
 }
 
 #[derive(Clone, Copy, Resource)]
@@ -173,24 +286,170 @@ impl From<RenderDistance> for u32 {
     }
 }
 
-fn when_texture_loads(events: Res<Events<AssetEvent<Image>>>, mut texture: ResMut<Assets<Image>>) {
-    for event in events.get_reader().iter(&events) {
-        match event {
-            AssetEvent::Created { handle } => {
-                texture.get_mut(handle).unwrap().sampler_descriptor = ImageSampler::nearest();
-                if let Descriptor(ref mut desc) =
-                    texture.get_mut(handle).unwrap().sampler_descriptor
-                {
-                    desc.min_filter = FilterMode::Linear;
-                    desc.anisotropy_clamp = std::num::NonZeroU8::new(16);
-                    desc.mipmap_filter = FilterMode::Linear;
-                    desc.mag_filter = FilterMode::Nearest;
-                }
-            }
-            AssetEvent::Modified { handle: _ } => (),
-            AssetEvent::Removed { handle: _ } => (),
+/// Adds the block texture array, sampled with sharp texels up close and smooth mip levels far away
+fn add_texture_map(textures: &mut Assets<Image>, mut texture_map: Image) -> Handle<Image> {
+    texture_map.sampler_descriptor = ImageSampler::nearest();
+    if let ImageSampler::Descriptor(ref mut desc) = texture_map.sampler_descriptor {
+        desc.min_filter = FilterMode::Linear;
+        desc.anisotropy_clamp = std::num::NonZeroU8::new(16);
+        desc.mipmap_filter = FilterMode::Linear;
+        desc.mag_filter = FilterMode::Nearest;
+        // Greedy meshes tile textures across merged faces
+        desc.address_mode_u = AddressMode::Repeat;
+        desc.address_mode_v = AddressMode::Repeat;
+    }
+    textures.add(texture_map)
+}
+
+/// Rebuilds the block textures and remeshes every chunk when the data pack is switched or one of
+/// its files changes
+#[allow(clippy::too_many_arguments)]
+fn reload_data_pack(
+    time: Res<Time>,
+    data_pack: Res<DataPack>,
+    mut watcher: ResMut<PackWatcher>,
+    mut textures: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<VoxelMaterial>>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut texture_map: ResMut<TextureMapHandle>,
+    mut texture_map_info: ResMut<TextureMapInfo>,
+    mut errors: ResMut<DataPackErrors>,
+    mut worldgen: ResMut<Worldgen>,
+) {
+    if !watcher.poll(&data_pack.0, time.delta()) {
+        return;
+    }
+    info!("Reloading data pack {}", data_pack.0);
+
+    // Blocks added to the pack cannot be registered until the world is opened again
+    let mut registry = Registry::clone(worldgen.registry());
+    let (image, info, pack_errors) = create_texture_map(&data_pack.0, &mut registry);
+    *errors = report_errors(&data_pack.0, pack_errors);
+    let handle = add_texture_map(&mut textures, image);
+    // Materials only rebind their textures when they change themselves, so point them at a new
+    // image instead of replacing the old one in place
+    for material in [
+        &chunk_materials.opaque,
+        &chunk_materials.cutout,
+        &chunk_materials.transparent,
+    ] {
+        if let Some(material) = materials.get_mut(material) {
+            material.textures = handle.clone();
         }
     }
+    texture_map.0 = handle;
+    *texture_map_info = info;
+    worldgen.remesh_all();
+}
+
+fn report_errors(data_pack: &str, errors: Vec<DataPackError>) -> DataPackErrors {
+    if !errors.is_empty() {
+        warn!("Data pack {} has {} problems", data_pack, errors.len());
+    }
+    for error in errors.iter() {
+        warn!("{}", error);
+    }
+    DataPackErrors(errors)
+}
+
+/// Number of data pack errors listed on screen, the rest are only logged
+const SHOWN_ERRORS: usize = 8;
+
+#[derive(Component)]
+struct DataPackErrorPanel;
+
+/// Lists the problems with the active data pack in the corner of the screen
+fn show_data_pack_errors(
+    mut commands: Commands,
+    errors: Res<DataPackErrors>,
+    panels: Query<Entity, With<DataPackErrorPanel>>,
+    menu_font: Res<MenuFont>,
+) {
+    if !errors.is_changed() {
+        return;
+    }
+    for panel in panels.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+    if errors.0.is_empty() {
+        return;
+    }
+
+    let style = |color| TextStyle {
+        font: menu_font.0.clone(),
+        font_size: 16.0,
+        color,
+    };
+    let mut sections = vec![TextSection::new(
+        format!("The data pack has {} problems:\n", errors.0.len()),
+        style(Color::rgb(1.0, 0.8, 0.3)),
+    )];
+    for error in errors.0.iter().take(SHOWN_ERRORS) {
+        sections.push(TextSection::new(
+            format!("{}\n", error),
+            style(Color::rgb(0.95, 0.95, 0.95)),
+        ));
+    }
+    if errors.0.len() > SHOWN_ERRORS {
+        sections.push(TextSection::new(
+            format!("and {} more, see the log", errors.0.len() - SHOWN_ERRORS),
+            style(Color::rgb(0.7, 0.7, 0.7)),
+        ));
+    }
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                max_size: Size::new(Val::Percent(60.0), Val::Auto),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.6)),
+            ..default()
+        })
+        .insert(DataPackErrorPanel)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_sections(sections));
+        });
+}
+
+fn hide_data_pack_errors(mut commands: Commands, panels: Query<Entity, With<DataPackErrorPanel>>) {
+    for panel in panels.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+}
+
+/// Switches to the next data pack under `assets/packs/` when F6 is pressed
+fn switch_data_pack(
+    keys: Res<Input<KeyCode>>,
+    mut data_pack: ResMut<DataPack>,
+    worldgen: Res<Worldgen>,
+) {
+    if !keys.just_pressed(KeyCode::F6) {
+        return;
+    }
+    let packs = available_packs();
+    let next = match packs.iter().position(|pack| *pack == data_pack.0) {
+        Some(index) => &packs[(index + 1) % packs.len()],
+        None => match packs.first() {
+            Some(pack) => pack,
+            None => return,
+        },
+    };
+    if *next == data_pack.0 {
+        return;
+    }
+
+    data_pack.0 = next.clone();
+    if let Err(e) = worldgen.save_data_pack(&data_pack.0) {
+        error!("Failed to save level.json: {}", e);
+    }
 }
 
 fn start_gravity(
@@ -1,41 +1,229 @@
-use std::sync::{atomic::AtomicU16, Arc};
+use std::{error::Error, fmt, sync::Arc};
 
-use dashmap::{mapref::one::Ref, DashMap};
+use bevy::{prelude::Resource, utils::HashMap};
 
-use crate::{game::BlockType, terrain::Biome};
+use crate::{
+    game::{BlockProperties, BlockType},
+    terrain::{Biome, Ore},
+};
 
-pub static BIOME_COUNT: AtomicU16 = AtomicU16::new(0);
-pub static BLOCK_COUNT: AtomicU16 = AtomicU16::new(0);
+use super::Block;
 
-lazy_static::lazy_static! {
+/// Block types, biomes and ores, which get their ids in the order they are registered.
+///
+/// Plugins register their content while the app starts up, after which the registry is frozen.
+/// Each world then registers the blocks of its data pack into a copy of its own, which is frozen
+/// before the world generates so ids cannot change under chunks being built.
+#[derive(Resource, Clone, Default)]
+pub struct Registry {
+    blocks: Vec<Arc<dyn BlockType>>,
+    block_ids: HashMap<String, u16>,
+    biomes: Vec<Arc<dyn Biome>>,
+    ores: Vec<Arc<dyn Ore>>,
+    frozen: bool,
+}
 
-pub static ref BIOMES: Arc<DashMap<u16, Box<dyn Biome>>> = Arc::new(DashMap::new());
-pub static ref BLOCKS: Arc<DashMap<u16, Box<dyn BlockType>>> = Arc::new(DashMap::new());
-pub static ref BLOCK_IDS: Arc<DashMap<String, u16>> = Arc::new(DashMap::new());
+impl Registry {
+    /// Returns an unfrozen copy of the registry, for a world to add its own content to
+    pub fn for_world(&self) -> Registry {
+        Registry {
+            frozen: false,
+            ..self.clone()
+        }
+    }
 
-}
+    /// Stops content from being registered, so ids handed out so far stay the same
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn register_biome(&mut self, biome: impl Biome + 'static) -> Result<u16, RegistryError> {
+        if self.frozen {
+            return Err(RegistryError::Frozen(biome.get_name().to_owned()));
+        }
+        self.biomes.push(Arc::new(biome));
+        Ok(self.biomes.len() as u16 - 1)
+    }
+
+    pub fn get_biome(&self, id: u16) -> Option<&dyn Biome> {
+        self.biomes.get(id as usize).map(|biome| biome.as_ref())
+    }
+
+    /// Id and biome of every registered biome
+    pub fn biomes(&self) -> impl Iterator<Item = (u16, &dyn Biome)> {
+        self.biomes
+            .iter()
+            .enumerate()
+            .map(|(id, biome)| (id as u16, biome.as_ref()))
+    }
+
+    pub fn biome_count(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn register_ore(&mut self, ore: impl Ore + 'static) -> Result<u16, RegistryError> {
+        if self.frozen {
+            return Err(RegistryError::Frozen(ore.get_name().to_owned()));
+        }
+        self.ores.push(Arc::new(ore));
+        Ok(self.ores.len() as u16 - 1)
+    }
+
+    /// Id and ore of every registered ore
+    pub fn ores(&self) -> impl Iterator<Item = (u16, &dyn Ore)> {
+        self.ores
+            .iter()
+            .enumerate()
+            .map(|(id, ore)| (id as u16, ore.as_ref()))
+    }
+
+    pub fn register_block(
+        &mut self,
+        block: impl BlockType + 'static,
+    ) -> Result<u16, RegistryError> {
+        let name = block.get_code_name();
+        if self.frozen {
+            return Err(RegistryError::Frozen(name.to_owned()));
+        }
+        if self.block_ids.contains_key(name) {
+            return Err(RegistryError::Duplicate(name.to_owned()));
+        }
+        let id = self.blocks.len() as u16;
+        self.block_ids.insert(name.to_owned(), id);
+        self.blocks.push(Arc::new(block));
+        Ok(id)
+    }
+
+    pub fn get_block(&self, id: u16) -> Option<&dyn BlockType> {
+        self.blocks.get(id as usize).map(|block| block.as_ref())
+    }
+
+    pub fn get_block_id(&self, name: &str) -> Option<u16> {
+        self.block_ids.get(name).copied()
+    }
+
+    /// Code name and id of every registered block type
+    pub fn block_ids(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.block_ids.iter().map(|(name, id)| (name.as_str(), *id))
+    }
 
-pub fn register_biome(biome: impl Biome + 'static) -> u16 {
-    let id = BIOME_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-    BIOMES.insert(id, Box::new(biome));
-    id
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the properties of a registered block type. Air is always id 0, even before any
+    /// block is registered.
+    pub fn get_block_properties(&self, block: Block) -> BlockProperties {
+        match self.get_block(block.id) {
+            Some(block_type) => block_type.get_properties(),
+            None if block.is_air() => BlockProperties::AIR,
+            None => BlockProperties::UNKNOWN,
+        }
+    }
 }
 
-pub fn get_biome(id: u16) -> Option<Ref<'static, u16, Box<dyn Biome>>> {
-    BIOMES.get(&id)
+#[derive(Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    /// Content was registered after the registry was frozen
+    Frozen(String),
+    /// A block type was registered under a code name that is already taken
+    Duplicate(String),
+    /// Content looked up a block by a code name that no block is registered under
+    Missing(String),
 }
 
-pub fn register_block(block: impl BlockType + 'static) -> u16 {
-    let id = BLOCK_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-    BLOCK_IDS.insert(block.get_code_name().to_string(), id);
-    BLOCKS.insert(id, Box::new(block));
-    id
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Frozen(name) => write!(
+                f,
+                "{} was registered after the registry was frozen, reopen the world to add it",
+                name
+            ),
+            RegistryError::Duplicate(name) => write!(f, "block {} is already registered", name),
+            RegistryError::Missing(name) => write!(f, "block {} is not registered", name),
+        }
+    }
 }
 
-pub fn get_block(id: u16) -> Option<Ref<'static, u16, Box<dyn BlockType>>> {
-    BLOCKS.get(&id)
+impl Error for RegistryError {}
+
+/// Snapshot of the properties of every registered block type, indexed by id
+#[derive(Default)]
+pub struct BlockPropertyTable(Vec<BlockProperties>);
+
+impl BlockPropertyTable {
+    pub fn new(registry: &Registry) -> Self {
+        Self(
+            (0..registry.block_count())
+                .map(|id| registry.get_block_properties(Block::new(id as u16)))
+                .collect(),
+        )
+    }
+
+    #[inline]
+    pub fn get(&self, block: Block) -> BlockProperties {
+        match self.0.get(block.id as usize) {
+            Some(properties) => *properties,
+            None if block.is_air() => BlockProperties::AIR,
+            None => BlockProperties::UNKNOWN,
+        }
+    }
 }
 
-pub fn get_block_id(name: &str) -> Option<u16> {
-    BLOCK_IDS.get(name).map(|r| *r)
+#[cfg(test)]
+mod tests {
+    use crate::game::{BlockType, RenderType};
+
+    use super::{Registry, RegistryError};
+
+    struct TestBlock(&'static str);
+
+    impl BlockType for TestBlock {
+        fn get_name(&self) -> &str {
+            self.0
+        }
+
+        fn get_durability(&self) -> f32 {
+            1.0
+        }
+
+        fn get_code_name(&self) -> &str {
+            self.0
+        }
+
+        fn get_render_type(&self) -> RenderType {
+            RenderType::Cutout
+        }
+    }
+
+    #[test]
+    fn test_register_after_freeze() {
+        let mut registry = Registry::default();
+        assert_eq!(registry.register_block(TestBlock("air")), Ok(0));
+        assert_eq!(registry.register_block(TestBlock("leaves")), Ok(1));
+        assert_eq!(
+            registry.register_block(TestBlock("leaves")),
+            Err(RegistryError::Duplicate("leaves".to_owned()))
+        );
+        registry.freeze();
+        assert_eq!(
+            registry.register_block(TestBlock("flower")),
+            Err(RegistryError::Frozen("flower".to_owned()))
+        );
+
+        // A world's copy takes more blocks without changing the registry it was made from
+        let mut world = registry.for_world();
+        assert_eq!(world.register_block(TestBlock("flower")), Ok(2));
+        assert_eq!(world.get_block_id("leaves"), Some(1));
+        assert_eq!(registry.get_block_id("flower"), None);
+        assert_eq!(
+            world.get_block(1).unwrap().get_render_type(),
+            RenderType::Cutout
+        );
+    }
 }
@@ -0,0 +1,482 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use bevy::{
+    log::{error, warn},
+    math::ivec3,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{terrain::TerrainSettings, util::ChunkCoord};
+
+use super::{Block, BlockStorage, Registry};
+
+/// Number of chunks along each axis of a region
+const REGION_SIZE: i32 = 8;
+const REGION_CHUNKS: u64 = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as u64;
+/// Each chunk has an (offset, length) pair of u32s in the region header
+const HEADER_ENTRY_SIZE: u64 = 8;
+/// Version 1 stored only block ids, version 2 added block states
+const CHUNK_FORMAT_VERSION: u8 = 2;
+
+/// World metadata stored in `level.json`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LevelInfo {
+    pub seed: u32,
+    pub data_pack: String,
+    /// Worlds saved before terrain settings existed generate with the defaults
+    #[serde(default)]
+    pub terrain: TerrainSettings,
+}
+
+/// On-disk storage for a single world under `saves/<name>/`.
+///
+/// Chunks are grouped into region files of 8x8x8 chunks. A region file starts with a table holding
+/// an (offset, length) pair for every chunk, followed by chunk payloads. A saved chunk is written
+/// over its old payload when it fits there, and appended otherwise, so the rest of the region is
+/// never rewritten.
+///
+/// Chunks are saved with the world's own block ids, listed by code name in `blocks.json`, and
+/// translated to and from the ids blocks were registered with in this run. Blocks can then be
+/// added or registered in another order without changing the blocks of saved chunks.
+pub struct WorldSave {
+    storage: Storage,
+    io_lock: Mutex<()>,
+    block_ids: Mutex<BlockIdMap>,
+}
+
+/// Where a world's chunks are kept
+enum Storage {
+    Disk(PathBuf),
+    /// Chunk payloads of a world that could not be opened on disk, which last until the game closes
+    Memory(Mutex<HashMap<ChunkCoord, Vec<u8>>>),
+}
+
+impl WorldSave {
+    pub fn open(name: &str) -> io::Result<WorldSave> {
+        WorldSave::open_dir(PathBuf::from(format!("saves/{}", name)))
+    }
+
+    fn open_dir(root: PathBuf) -> io::Result<WorldSave> {
+        fs::create_dir_all(root.join("region"))?;
+
+        let saved_ids = match File::open(root.join("blocks.json")) {
+            Ok(file) => serde_json::from_reader(io::BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(WorldSave {
+            storage: Storage::Disk(root),
+            io_lock: Mutex::new(()),
+            block_ids: Mutex::new(BlockIdMap::new(saved_ids)),
+        })
+    }
+
+    /// Creates a save that keeps chunks in memory and writes nothing to disk
+    pub fn in_memory() -> WorldSave {
+        WorldSave {
+            storage: Storage::Memory(Mutex::new(HashMap::new())),
+            io_lock: Mutex::new(()),
+            block_ids: Mutex::new(BlockIdMap::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn load_level(&self) -> Option<LevelInfo> {
+        let Storage::Disk(root) = &self.storage else {
+            return None;
+        };
+        let file = File::open(root.join("level.json")).ok()?;
+        match serde_json::from_reader(file) {
+            Ok(level) => Some(level),
+            Err(e) => {
+                error!("Error parsing level.json: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn save_level(&self, level: &LevelInfo) -> io::Result<()> {
+        let Storage::Disk(root) = &self.storage else {
+            return Ok(());
+        };
+        let file = File::create(root.join("level.json"))?;
+        serde_json::to_writer_pretty(file, level).map_err(io::Error::from)
+    }
+
+    /// Loads a chunk from its region file, returning None if it was never saved
+    pub fn load_chunk(&self, coord: ChunkCoord, registry: &Registry) -> Option<BlockStorage> {
+        match self.read_chunk(coord) {
+            Ok(Some(payload)) => {
+                let data = match payload.split_first() {
+                    Some((&CHUNK_FORMAT_VERSION, bytes)) => BlockStorage::read_bytes(bytes),
+                    Some((1, bytes)) => BlockStorage::read_bytes_without_states(bytes),
+                    _ => None,
+                };
+                let Some(mut data) = data else {
+                    error!("Corrupt chunk {} in world save", coord);
+                    return None;
+                };
+                let block_ids = self.block_ids(registry);
+                data.map_blocks(|block| block_ids.to_registered(block));
+                Some(data)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                error!("Error reading chunk {} from world save: {}", coord, e);
+                None
+            }
+        }
+    }
+
+    pub fn save_chunk(
+        &self,
+        coord: ChunkCoord,
+        data: &BlockStorage,
+        registry: &Registry,
+    ) -> io::Result<()> {
+        let mut data = data.clone();
+        {
+            let block_ids = self.block_ids(registry);
+            data.map_blocks(|block| block_ids.to_saved(block));
+        }
+        let mut payload = vec![CHUNK_FORMAT_VERSION];
+        data.write_bytes(&mut payload);
+        self.write_chunk(coord, payload)
+    }
+
+    fn read_chunk(&self, coord: ChunkCoord) -> io::Result<Option<Vec<u8>>> {
+        let _lock = self.io_lock.lock().unwrap();
+        match &self.storage {
+            Storage::Disk(root) => {
+                let (region, index) = region_of(coord);
+                match File::open(region_path(root, region)) {
+                    Ok(mut file) => read_payload(&mut file, index),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                    Err(e) => Err(e),
+                }
+            }
+            Storage::Memory(chunks) => Ok(chunks.lock().unwrap().get(&coord).cloned()),
+        }
+    }
+
+    fn write_chunk(&self, coord: ChunkCoord, payload: Vec<u8>) -> io::Result<()> {
+        let _lock = self.io_lock.lock().unwrap();
+        match &self.storage {
+            Storage::Disk(root) => {
+                let (region, index) = region_of(coord);
+                let mut file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(region_path(root, region))?;
+                write_payload(&mut file, index, &payload)
+            }
+            Storage::Memory(chunks) => {
+                chunks.lock().unwrap().insert(coord, payload);
+                Ok(())
+            }
+        }
+    }
+
+    /// Locks the world's block ids, first giving ids to blocks registered since they were used
+    fn block_ids(&self, registry: &Registry) -> MutexGuard<'_, BlockIdMap> {
+        let mut block_ids = self.block_ids.lock().unwrap();
+        if block_ids.update(registry) {
+            if let Storage::Disk(root) = &self.storage {
+                if let Err(e) = block_ids.save(&root.join("blocks.json")) {
+                    error!("Failed to save blocks.json: {}", e);
+                }
+            }
+        }
+        block_ids
+    }
+}
+
+/// Ids of blocks in a world save, and how they translate to the ids of registered blocks.
+///
+/// Translations are made from the registry chunks are first loaded or saved with, which holds the
+/// blocks of the world's data pack.
+struct BlockIdMap {
+    /// Saved id of every block that was ever saved in the world, by code name
+    saved_ids: BTreeMap<String, u16>,
+    to_saved: HashMap<u16, u16>,
+    to_registered: HashMap<u16, u16>,
+}
+
+impl BlockIdMap {
+    fn new(saved_ids: BTreeMap<String, u16>) -> Self {
+        BlockIdMap {
+            saved_ids,
+            to_saved: HashMap::new(),
+            to_registered: HashMap::new(),
+        }
+    }
+
+    /// Translates the ids of blocks registered since the last update, giving new saved ids to
+    /// blocks the world has not seen before. Returns true if any were given.
+    fn update(&mut self, registry: &Registry) -> bool {
+        if self.to_saved.len() == registry.block_count() {
+            return false;
+        }
+        let first_update = self.to_saved.is_empty();
+
+        // Worlds saved before they had their own ids used the ids blocks are registered with
+        let mut added = self.saved_ids.is_empty();
+        if added {
+            self.saved_ids = registry
+                .block_ids()
+                .map(|(name, id)| (name.to_owned(), id))
+                .collect();
+        }
+
+        let mut next_id = self.saved_ids.values().max().map_or(0, |id| id + 1);
+        for (name, registered) in registry.block_ids() {
+            let saved = match self.saved_ids.get(name) {
+                Some(id) => *id,
+                None => {
+                    self.saved_ids.insert(name.to_owned(), next_id);
+                    added = true;
+                    next_id += 1;
+                    next_id - 1
+                }
+            };
+            self.to_saved.insert(registered, saved);
+            self.to_registered.insert(saved, registered);
+        }
+
+        if first_update {
+            for (name, id) in self.saved_ids.iter() {
+                if !self.to_registered.contains_key(id) {
+                    warn!(
+                        "Block {} in world save is not registered, loading it as air",
+                        name
+                    );
+                }
+            }
+        }
+        added
+    }
+
+    fn to_saved(&self, block: Block) -> Block {
+        match self.to_saved.get(&block.id) {
+            Some(id) => Block::with_state(*id, block.state),
+            // Air is the only block used without being registered
+            None => block,
+        }
+    }
+
+    fn to_registered(&self, block: Block) -> Block {
+        match self.to_registered.get(&block.id) {
+            Some(id) => Block::with_state(*id, block.state),
+            None if block.is_air() => block,
+            None => Block::air(),
+        }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &self.saved_ids).map_err(io::Error::from)
+    }
+}
+
+fn read_payload(file: &mut File, index: u64) -> io::Result<Option<Vec<u8>>> {
+    let (offset, len) = read_entry(file, index)?;
+    if offset == 0 {
+        return Ok(None);
+    }
+
+    let mut payload = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Writes a chunk's payload over its old one if it fits there or the old one ends the file, and
+/// appends it to the region otherwise
+fn write_payload(file: &mut File, index: u64, payload: &[u8]) -> io::Result<()> {
+    if file.metadata()?.len() < REGION_CHUNKS * HEADER_ENTRY_SIZE {
+        file.set_len(REGION_CHUNKS * HEADER_ENTRY_SIZE)?;
+    }
+
+    let (old_offset, old_len) = read_entry(file, index)?;
+    let (old_offset, old_len) = (old_offset as u64, old_len as u64);
+    let end = file.seek(SeekFrom::End(0))?;
+    let len = payload.len() as u64;
+    let at_end = old_offset != 0 && old_offset + old_len == end;
+    let offset = if old_offset != 0 && (len <= old_len || at_end) {
+        old_offset
+    } else {
+        end
+    };
+
+    // Header entries are u32s, so a region ends at 4 GiB
+    let too_large = |_| io::Error::other("region file would be larger than 4 GiB");
+    let entry_offset = u32::try_from(offset).map_err(too_large)?;
+    let entry_len = u32::try_from(len).map_err(too_large)?;
+    u32::try_from(offset + len).map_err(too_large)?;
+
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(payload)?;
+    if at_end && len < old_len {
+        file.set_len(offset + len)?;
+    }
+
+    let mut entry = [0; HEADER_ENTRY_SIZE as usize];
+    entry[..4].copy_from_slice(&entry_offset.to_le_bytes());
+    entry[4..].copy_from_slice(&entry_len.to_le_bytes());
+    file.seek(SeekFrom::Start(index * HEADER_ENTRY_SIZE))?;
+    file.write_all(&entry)
+}
+
+/// Reads the (offset, length) pair of a chunk from the region header
+fn read_entry(file: &mut File, index: u64) -> io::Result<(u32, u32)> {
+    let mut entry = [0; HEADER_ENTRY_SIZE as usize];
+    file.seek(SeekFrom::Start(index * HEADER_ENTRY_SIZE))?;
+    file.read_exact(&mut entry)?;
+
+    let offset = u32::from_le_bytes(entry[..4].try_into().unwrap());
+    let len = u32::from_le_bytes(entry[4..].try_into().unwrap());
+    Ok((offset, len))
+}
+
+fn region_path(root: &Path, region: ChunkCoord) -> PathBuf {
+    root.join("region")
+        .join(format!("r.{}.{}.{}.bin", region.x, region.y, region.z))
+}
+
+/// Returns the region containing a chunk and the chunk's index within that region
+fn region_of(coord: ChunkCoord) -> (ChunkCoord, u64) {
+    let region = ivec3(
+        coord.x.div_euclid(REGION_SIZE),
+        coord.y.div_euclid(REGION_SIZE),
+        coord.z.div_euclid(REGION_SIZE),
+    );
+    let local = coord - region * REGION_SIZE;
+    let index = (local.x * REGION_SIZE + local.y) * REGION_SIZE + local.z;
+    (region, index as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bevy::math::ivec3;
+
+    use super::{region_of, LevelInfo, WorldSave, REGION_CHUNKS};
+    use crate::{
+        game::{BlockType, RenderType},
+        loader::{Block, BlockStorage, Registry},
+    };
+
+    struct TestBlock(&'static str);
+
+    impl BlockType for TestBlock {
+        fn get_name(&self) -> &str {
+            self.0
+        }
+
+        fn get_durability(&self) -> f32 {
+            1.0
+        }
+
+        fn get_code_name(&self) -> &str {
+            self.0
+        }
+
+        fn get_render_type(&self) -> RenderType {
+            RenderType::Opaque
+        }
+    }
+
+    fn registry(names: &[&'static str]) -> Registry {
+        let mut registry = Registry::default();
+        for name in names {
+            registry.register_block(TestBlock(name)).unwrap();
+        }
+        registry
+    }
+
+    #[test]
+    fn test_region_index() {
+        assert_eq!(region_of(ivec3(0, 0, 0)), (ivec3(0, 0, 0), 0));
+        assert_eq!(region_of(ivec3(-1, 0, 8)), (ivec3(-1, 0, 1), 448));
+        assert_eq!(region_of(ivec3(15, -9, 7)), (ivec3(1, -2, 0), 511));
+
+        // Every chunk of a region has its own index
+        let mut indices: Vec<u64> = (0..8)
+            .flat_map(|x| (0..8).flat_map(move |y| (0..8).map(move |z| ivec3(x, y, z) - 8)))
+            .map(|coord| {
+                let (region, index) = region_of(coord);
+                assert_eq!(region, ivec3(-1, -1, -1));
+                index
+            })
+            .collect();
+        indices.sort_unstable();
+        assert!(indices.iter().copied().eq(0..REGION_CHUNKS));
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let root = std::env::temp_dir().join(format!("vixen_save_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let region = root.join("region").join("r.0.0.0.bin");
+
+        let registry = registry(&["air", "stone", "dirt"]);
+        let (stone, dirt) = (Block::new(1), Block::with_state(2, 3));
+        let mut data = BlockStorage::default();
+        data.set((0, 0, 0), stone);
+        data.set((5, 6, 7), dirt);
+        let level = LevelInfo {
+            seed: 42,
+            data_pack: "classic".to_owned(),
+            terrain: Default::default(),
+        };
+        {
+            let save = WorldSave::open_dir(root.clone()).unwrap();
+            save.save_level(&level).unwrap();
+            save.save_chunk(ivec3(1, 2, 3), &data, &registry).unwrap();
+            save.save_chunk(ivec3(1, 2, 4), &data, &registry).unwrap();
+
+            // Saving a chunk again reuses its slot instead of growing the region
+            let len = fs::metadata(&region).unwrap().len();
+            data.set((5, 6, 7), Block::air());
+            save.save_chunk(ivec3(1, 2, 3), &data, &registry).unwrap();
+            assert_eq!(fs::metadata(&region).unwrap().len(), len);
+        }
+
+        // Blocks keep their names when they are registered in another order
+        let registry = registry(&["air", "dirt", "stone"]);
+        let save = WorldSave::open_dir(root.clone()).unwrap();
+        assert_eq!(save.load_level().unwrap().seed, 42);
+        let loaded = save.load_chunk(ivec3(1, 2, 3), &registry).unwrap();
+        assert_eq!(loaded.get((0, 0, 0)), Block::new(2));
+        assert_eq!(loaded.get((5, 6, 7)), Block::air());
+        let loaded = save.load_chunk(ivec3(1, 2, 4), &registry).unwrap();
+        assert_eq!(loaded.get((5, 6, 7)), Block::with_state(1, 3));
+        assert!(save.load_chunk(ivec3(1, 2, 5), &registry).is_none());
+        assert!(save.load_chunk(ivec3(9, 2, 5), &registry).is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_save() {
+        let registry = registry(&["air", "stone"]);
+        let mut data = BlockStorage::default();
+        data.set((1, 1, 1), Block::new(1));
+
+        let save = WorldSave::in_memory();
+        save.save_chunk(ivec3(0, -1, 0), &data, &registry).unwrap();
+        let loaded = save.load_chunk(ivec3(0, -1, 0), &registry).unwrap();
+        assert_eq!(loaded.get((1, 1, 1)), Block::new(1));
+        assert!(save.load_level().is_none());
+    }
+}
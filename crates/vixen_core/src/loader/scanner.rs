@@ -4,6 +4,12 @@ use crate::util::{to_chunk_coord, to_world_coord};
 
 use super::ChunkCoord;
 
+/// Distance in chunks within which chunks are meshed at full detail. Each following level of detail
+/// reaches twice as far as the one before.
+const FULL_DETAIL_DISTANCE: f32 = 4.0;
+/// Coarsest level of detail, where a mesh cell spans 2^MAX_LOD_LEVEL blocks
+const MAX_LOD_LEVEL: u8 = 3;
+
 #[derive(Component, Clone)]
 pub struct ChunkScanner {
     range: u32,
@@ -39,12 +45,29 @@ impl ChunkScanner {
     }
 
     pub fn should_load_mesh(&self, pos: &ChunkCoord) -> bool {
+        self.lod_level(pos).is_some()
+    }
+
+    /// Level of detail to mesh a chunk with, where level n merges 2^n blocks along each axis into
+    /// a single cell, or None if the chunk is out of range
+    pub fn lod_level(&self, pos: &ChunkCoord) -> Option<u8> {
         let pos = to_world_coord(pos);
         let lhs: f32 = (pos.x - self.center.x).powf(2.0)
             + (pos.y - self.center.y).powf(2.0)
             + (pos.z - self.center.z).powf(2.0);
         let rhs: f32 = (self.range as usize * 32).pow(2) as f32;
-        lhs <= rhs
+        if lhs > rhs {
+            return None;
+        }
+
+        let distance = lhs.sqrt() / 32.0;
+        let mut level = 0;
+        let mut reach = FULL_DETAIL_DISTANCE;
+        while distance >= reach && level < MAX_LOD_LEVEL {
+            level += 1;
+            reach *= 2.0;
+        }
+        Some(level)
     }
 
     pub fn get_center(&self) -> ChunkCoord {
@@ -1,150 +1,323 @@
-use std::{fs::File, path::PathBuf};
+use std::{
+    collections::HashMap as StdHashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
 
 use bevy::{
+    log::{info, warn},
     prelude::{Handle, Image, Resource},
-    render::{
-        render_resource::{Extent3d, TextureDimension},
-        texture::dds_format_to_texture_format,
+    render::render_resource::{
+        Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
     },
     utils::HashMap,
 };
-use ddsfile::{Dds, Error};
 use serde::{Deserialize, Serialize};
 
-use super::get_block_id;
+use crate::game::{get_state_value, state_count, StateProperty};
+
+use super::{
+    chunk::Faces,
+    data_block::{BlockDefinition, DataBlock},
+    mipmap::MipChain,
+    model::{BlockModel, ModelDefinition},
+    pack::{resolve_pack, DataPackError, DataPackErrorKind, PackLayer},
+    vertex::MAX_LAYERS,
+    Block, Registry,
+};
+
+/// The texture shown on a block face
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FaceTexture {
+    /// Layer of the block texture array
+    pub layer: u32,
+    /// Texture coordinates of the face's corners, in the order of its points
+    pub uvs: [[f32; 2]; 4],
+}
+
+pub type FaceTextures = [FaceTexture; 6];
 
 #[derive(Resource)]
 pub struct TextureMapHandle(pub Handle<Image>);
 
+/// Textures of every face of every block state
 #[derive(Resource)]
-pub struct TextureMapInfo(pub HashMap<u16, [[[f32; 2]; 4]; 6]>);
+pub struct TextureMapInfo {
+    pub faces: HashMap<Block, FaceTextures>,
+    /// Models of the block states that are not plain cubes
+    pub models: HashMap<Block, BlockModel>,
+}
+
+/// Format of the block texture array. PNG textures and the missing texture are decoded to it, and
+/// DDS textures have to be stored in it.
+const TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Layer of the checkerboard texture drawn in place of the textures a data pack is missing
+pub const MISSING_LAYER: u32 = 0;
 
+/// Maps the textures of every block state to their layers in `face_map`, reading the blocks of
+/// every pack in `layers` and registering the ones that are not in `registry` yet. Blocks and
+/// textures that cannot be resolved are drawn with `MISSING_LAYER` and reported in `errors`.
 pub fn gen_texture_map_info(
     face_map: HashMap<String, u32>,
     data_pack: &str,
-    num: u32,
+    layers: &[PackLayer],
+    registry: &mut Registry,
+    errors: &mut Vec<DataPackError>,
 ) -> TextureMapInfo {
-    let block_data = load_block_data(data_pack);
+    // Blocks of higher priority packs replace the ones of their parents
+    let mut block_data = HashMap::new();
+    for layer in layers.iter().rev() {
+        let path = layer.dir.join("blocks.json");
+        match load_block_data(&path) {
+            Ok(Some(blocks)) => block_data.extend(
+                blocks
+                    .into_iter()
+                    .map(|(name, textures)| (name, (path.clone(), textures))),
+            ),
+            Ok(None) => (),
+            Err(e) => errors.push(e),
+        }
+    }
+    let mut names: Vec<&String> = block_data.keys().collect();
+    names.sort();
 
-    let num = num as f32;
     let mut map = HashMap::new();
-    for (name, block_textures) in block_data.iter() {
-        let mut faces = [[[0.0; 2]; 4]; 6];
-        for i in 0..6 {
-            let Some(loc) = face_map.get(block_textures.get(i)) else { panic!("Invalid texture name: {}", block_textures.get(i))};
-            let top = *loc as f32 / num;
-            let bottom = (loc + 1) as f32 / num;
-            
-            faces[i] = [
-                [1.0, bottom],//- 0.5 / height],
-                [1.0, top],// + 0.5 / height],
-                [0.0, top],// + 0.5 / height],
-                [0.0, bottom]// - 0.5 / height],
-            ];
-        }
-        let block_id = get_block_id(name).unwrap();
-        map.insert(block_id, faces);
-    }
-
-    TextureMapInfo(map)
+    let mut models = HashMap::new();
+    for name in names {
+        let (path, block_textures) = &block_data[name];
+        // Blocks the code does not know about are defined by the pack
+        let block_id = match registry.get_block_id(name) {
+            Some(block_id) => block_id,
+            None => match DataBlock::new(name, &block_textures.definition, &block_textures.model)
+                .and_then(|block| {
+                    registry
+                        .register_block(block)
+                        .map_err(DataPackErrorKind::Registry)
+                }) {
+                Ok(block_id) => block_id,
+                Err(e) => {
+                    errors.push(DataPackError::new(path, e).with_block(name));
+                    continue;
+                }
+            },
+        };
+        let (properties, full_cube) = match registry.get_block(block_id) {
+            Some(block_type) => (block_type.get_state_properties(), block_type.is_full_cube()),
+            None => (Default::default(), true),
+        };
+        let state_count = match state_count(properties) {
+            Ok(state_count) => state_count,
+            Err(e) => {
+                let error = DataPackErrorKind::TooManyStates(e);
+                errors.push(DataPackError::new(path, error).with_block(name));
+                continue;
+            }
+        };
+
+        let mut unknown = Vec::new();
+        for state in 0..state_count {
+            let (textures, rotation, definition) = block_textures.resolve(properties, state);
+            let mut faces = [FaceTexture::default(); 6];
+            for i in 0..6 {
+                let layer = match face_map.get(textures[i]) {
+                    Some(layer) => *layer,
+                    None => {
+                        if !unknown.contains(&textures[i]) {
+                            unknown.push(textures[i]);
+                        }
+                        MISSING_LAYER
+                    }
+                };
+                faces[i] = FaceTexture {
+                    layer,
+                    uvs: [[1.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0]],
+                };
+            }
+
+            let block = Block::with_state(block_id, state);
+            if let Some(model) = BlockModel::new(definition, &faces, &rotation_matrix(rotation)) {
+                if full_cube {
+                    warn!(
+                        "{} has a {:?} model but its block type is a full cube",
+                        name, definition
+                    );
+                }
+                models.insert(block, model);
+            }
+            map.insert(block, rotate_faces(&faces, rotation));
+        }
+        for texture in unknown {
+            let error = DataPackErrorKind::UnknownTexture(texture.to_owned());
+            errors.push(DataPackError::new(path, error).with_block(name));
+        }
+    }
+
+    // Meshing looks up the textures of every block it draws
+    let path = PathBuf::from(format!("assets/packs/{}/blocks.json", data_pack));
+    let mut missing: Vec<(&str, u16)> = registry
+        .block_ids()
+        .filter(|(name, id)| {
+            !block_data.contains_key(*name)
+                && registry.get_block_properties(Block::new(*id)).is_visible()
+        })
+        .collect();
+    missing.sort();
+    for (name, id) in missing {
+        errors.push(DataPackError::new(&path, DataPackErrorKind::MissingBlock).with_block(name));
+        let properties = match registry.get_block(id) {
+            Some(block_type) => block_type.get_state_properties(),
+            None => Default::default(),
+        };
+        for state in 0..state_count(properties).unwrap_or(1) {
+            map.insert(Block::with_state(id, state), [MISSING_FACE; 6]);
+        }
+    }
+
+    TextureMapInfo { faces: map, models }
 }
 
-pub fn create_texture_map(data_pack: &str) -> (Image, TextureMapInfo) {
-    let path = format!("assets/packs/{}/textures/", data_pack);
-    let files_in_dir = dds_files_in_dir(&path);
-    let mut images = Vec::new();
-    let (mut width, mut height, mut num_layers, mut mipmap_levels, mut texture_format, mut depth) =
-        (None, None, None, None, None, None);
+const MISSING_FACE: FaceTexture = FaceTexture {
+    layer: MISSING_LAYER,
+    uvs: [[1.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0]],
+};
+
+/// Builds the block texture array of a data pack and the packs it inherits from, along with the
+/// layers of every block state.
+///
+/// The first layer holds a generated checkerboard for `MISSING_LAYER`. Every texture has to match
+/// its size and 8 bit sRGB format, and textures that do not are left out. Problems with the pack
+/// are returned instead of stopping the load. Blocks the pack defines are registered in `registry`.
+pub fn create_texture_map(
+    data_pack: &str,
+    registry: &mut Registry,
+) -> (Image, TextureMapInfo, Vec<DataPackError>) {
+    let mut errors = Vec::new();
+    let layers = resolve_pack(data_pack, &mut errors);
+    for layer in layers.iter() {
+        info!(
+            "Loading data pack {} {} from {}",
+            layer.manifest.name,
+            layer.manifest.version,
+            layer.dir.display()
+        );
+    }
+
+    // Textures of higher priority packs replace the ones of their parents
+    let mut files = HashMap::new();
+    for layer in layers.iter().rev() {
+        let path = layer.dir.join("textures");
+        match texture_files_in_dir(&path) {
+            Ok(layer_files) => files.extend(layer_files),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => errors.push(DataPackError::new(path, DataPackErrorKind::Io(e))),
+        }
+    }
+    let mut files: Vec<_> = files.into_iter().collect();
+    files.sort();
+
+    let mut textures: Vec<MipChain> = Vec::new();
     let mut face_map = HashMap::new();
-    let mut id = 0;
-    for file in files_in_dir {
-        let Ok(dds) = load_dds(&file) else { continue; };
-        if width.is_none() {
-            width = Some(dds.get_width());
-            height = Some(dds.get_height());
-            num_layers = Some(dds.get_num_array_layers());
-            texture_format = Some(dds_format_to_texture_format(&dds, true).unwrap());
-            mipmap_levels = Some(dds.get_num_mipmap_levels());
-            depth = Some(dds.get_depth());
-        } else {
-            assert_eq!(width, Some(dds.get_width()));
-            assert_eq!(height, Some(dds.get_height()));
-            assert_eq!(num_layers, Some(dds.get_num_array_layers()));
-            assert_eq!(
-                texture_format,
-                Some(dds_format_to_texture_format(&dds, true).unwrap())
-            );
-            assert_eq!(mipmap_levels, Some(dds.get_num_mipmap_levels()));
-            assert_eq!(depth, Some(dds.get_depth()));
+    for (name, file) in files {
+        let texture = match file.extension().and_then(|extension| extension.to_str()) {
+            Some("dds") => MipChain::from_dds(&file),
+            _ => MipChain::from_png(&file),
+        };
+        let texture = match texture {
+            Ok(texture) => texture,
+            Err(e) => {
+                errors.push(DataPackError::new(file, DataPackErrorKind::Io(e)));
+                continue;
+            }
+        };
+        let size = (texture.width, texture.height, texture.format);
+        let expected = match textures.first() {
+            Some(first) => (first.width, first.height, TEXTURE_FORMAT),
+            None => (texture.width, texture.height, TEXTURE_FORMAT),
+        };
+        if size != expected {
+            errors.push(DataPackError::new(
+                file,
+                DataPackErrorKind::TextureMismatch { size, expected },
+            ));
+            continue;
+        }
+        // The missing texture takes up the first layer
+        if textures.len() + 1 == MAX_LAYERS as usize {
+            errors.push(DataPackError::new(file, DataPackErrorKind::TooManyTextures));
+            continue;
         }
-        images.push(dds);
-        face_map.insert(file.file_stem().unwrap().to_str().unwrap().to_owned(), id);
-        id += 1;
+        face_map.insert(name, textures.len() as u32 + 1);
+        textures.push(texture);
     }
 
+    let (width, height) = match textures.first() {
+        Some(first) => (first.width, first.height),
+        None => (16, 16),
+    };
+    textures.insert(
+        MISSING_LAYER as usize,
+        MipChain::checkerboard(width, height),
+    );
+    // Textures can come with different numbers of mip levels, so keep the ones they all have
+    let mipmap_levels = textures
+        .iter()
+        .map(|texture| texture.levels.len() as u32)
+        .min()
+        .unwrap();
+
     let mut image = Image::default();
     image.texture_descriptor.size = Extent3d {
-        width: width.unwrap(),
-        height: height.unwrap() * images.len() as u32,
-        depth_or_array_layers: depth.unwrap(),
-    }
-    .physical_size(texture_format.unwrap());
-    image.texture_descriptor.mip_level_count = mipmap_levels.unwrap();
-    image.texture_descriptor.format = texture_format.unwrap();
-    image.texture_descriptor.dimension = if depth.unwrap() > 1 {
-        TextureDimension::D3
-    } else if image.is_compressed() || height.unwrap() > 1 {
-        TextureDimension::D2
-    } else {
-        TextureDimension::D1
-    };
+        width,
+        height,
+        depth_or_array_layers: textures.len() as u32,
+    }
+    .physical_size(TEXTURE_FORMAT);
+    image.texture_descriptor.mip_level_count = mipmap_levels;
+    image.texture_descriptor.format = TEXTURE_FORMAT;
+    image.texture_descriptor.dimension = TextureDimension::D2;
+    // A pack with a single texture would otherwise be viewed as a plain 2D texture
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..Default::default()
+    });
 
-    // Stitch all the images together
+    // Each layer holds all of its mip levels before the next layer starts
     let mut data = Vec::new();
-    let mipmap_levels = mipmap_levels.unwrap();
-    for mipmap_level in 0..mipmap_levels {
-        for image in images.iter() {
-            let image_data = image.get_data(0).unwrap();
-            let (start, end) =
-                get_mipmap_size(image.get_main_texture_size().unwrap(), mipmap_level);
-            data.extend_from_slice(&image_data[start..end]);
+    for texture in textures.iter() {
+        for level in &texture.levels[..mipmap_levels as usize] {
+            data.extend_from_slice(level);
         }
     }
     image.data = data;
 
-    (
-        image,
-        gen_texture_map_info(face_map, data_pack, images.len() as u32),
-    )
+    let texture_map_info =
+        gen_texture_map_info(face_map, data_pack, &layers, registry, &mut errors);
+    (image, texture_map_info, errors)
 }
 
-fn dds_files_in_dir(path: &str) -> Vec<PathBuf> {
-    let mut files = Vec::new();
-    for entry in std::fs::read_dir(path).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        if path.extension().unwrap() == "dds" {
-            files.push(path);
+/// Returns the name and path of every DDS and PNG texture in a directory. A texture that comes in
+/// both formats is read from the DDS file, which has its mip levels made ahead of time.
+fn texture_files_in_dir(path: &Path) -> io::Result<HashMap<String, PathBuf>> {
+    let mut files: HashMap<String, PathBuf> = HashMap::new();
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        let (Some(name), Some(extension)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        let name = name.to_string_lossy().into_owned();
+        match extension.to_str() {
+            Some("dds") => {
+                files.insert(name, path);
+            }
+            Some("png") => {
+                files.entry(name).or_insert(path);
+            }
+            _ => (),
         }
     }
-    files
-}
-
-fn load_dds(path: &PathBuf) -> Result<Dds, Error> {
-    let file = std::fs::File::open(path).unwrap();
-    let mut reader = std::io::BufReader::new(file);
-    let dds = Dds::read(&mut reader);
-    dds
-}
-
-fn get_mipmap_size(main_size: u32, mipmap_level: u32) -> (usize, usize) {
-    let mut start = 0;
-    let mut size = main_size;
-    for _ in 0..mipmap_level {
-        start += size;
-        size /= 4;
-    }
-    (start as usize, (start + size) as usize)
+    Ok(files)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -155,26 +328,76 @@ pub struct BlockTextures {
     pub right: String,
     pub front: String,
     pub back: String,
+    /// Changes applied to the block's states that match, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<BlockVariant>,
+    #[serde(default)]
+    pub model: ModelDefinition,
+    #[serde(flatten)]
+    pub definition: BlockDefinition,
 }
 
-pub fn load_block_data(data_pack: &str) -> HashMap<String, BlockTextures> {
-    let path = format!("assets/packs/{}/blocks.json", data_pack);
-    let blocks = match File::open(&path) {
+/// Textures and rotation for the states of a block whose properties have the given values
+#[derive(Serialize, Deserialize)]
+pub struct BlockVariant {
+    pub when: StdHashMap<String, String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub front: Option<String>,
+    pub back: Option<String>,
+    pub model: Option<ModelDefinition>,
+    /// Rotation of the block around the x axis in degrees, applied before `y`
+    pub x: Option<i32>,
+    /// Rotation of the block around the y axis in degrees
+    pub y: Option<i32>,
+}
+
+/// Reads the blocks of a `blocks.json` file, or returns None if the pack does not have one
+pub fn load_block_data(
+    path: &Path,
+) -> Result<Option<HashMap<String, BlockTextures>>, DataPackError> {
+    let blocks = match File::open(path) {
         Ok(blocks) => blocks,
-        Err(e) => {
-            panic!("Error opening {}: {}", path, e);
-        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(DataPackError::new(path, DataPackErrorKind::Io(e))),
     };
+    serde_json::from_reader(BufReader::new(blocks))
+        .map(Some)
+        .map_err(|e| DataPackError::new(path, DataPackErrorKind::Json(e)))
+}
 
-    match serde_json::from_reader(blocks) {
-        Ok(v) => v,
-        Err(e) => {
-            panic!("Error parsing blocks.json: {}", e);
+impl BlockTextures {
+    /// Returns the texture of every face, the (x, y) rotation and the model of a block state
+    pub fn resolve(
+        &self,
+        properties: &[StateProperty],
+        state: u16,
+    ) -> ([&str; 6], (i32, i32), &ModelDefinition) {
+        let mut textures = [0, 1, 2, 3, 4, 5].map(|id| self.get(id));
+        let mut rotation = (0, 0);
+        let mut model = &self.model;
+        for variant in self.variants.iter() {
+            let matches = variant.when.iter().all(|(name, value)| {
+                get_state_value(properties, state, name) == Some(value.as_str())
+            });
+            if !matches {
+                continue;
+            }
+
+            for (id, texture) in textures.iter_mut().enumerate() {
+                if let Some(replacement) = variant.get(id) {
+                    *texture = replacement;
+                }
+            }
+            rotation.0 = variant.x.unwrap_or(rotation.0);
+            rotation.1 = variant.y.unwrap_or(rotation.1);
+            model = variant.model.as_ref().unwrap_or(model);
         }
+        (textures, rotation, model)
     }
-}
 
-impl BlockTextures {
     pub fn get(&self, id: usize) -> &str {
         match id {
             0 => &self.left,
@@ -187,3 +410,145 @@ impl BlockTextures {
         }
     }
 }
+
+impl BlockVariant {
+    /// Same face order as `BlockTextures::get`
+    pub fn get(&self, id: usize) -> Option<&str> {
+        match id {
+            0 => self.left.as_deref(),
+            1 => self.right.as_deref(),
+            2 => self.bottom.as_deref(),
+            3 => self.top.as_deref(),
+            4 => self.front.as_deref(),
+            5 => self.back.as_deref(),
+            _ => panic!("Invalid face id"),
+        }
+    }
+}
+
+pub(super) type Matrix = [[i32; 3]; 3];
+
+/// Rotates the texture coordinates of a block's faces by the (x, y) rotation in degrees, so each
+/// face shows the texture of the face that the rotation moved onto it
+fn rotate_faces(faces: &FaceTextures, rotation: (i32, i32)) -> FaceTextures {
+    let rotation = rotation_matrix(rotation);
+
+    // A rotation's inverse is its transpose
+    let inverse = |v: [i32; 3]| -> [i32; 3] {
+        [0, 1, 2].map(|i| (0..3).map(|j| rotation[j][i] * v[j]).sum())
+    };
+
+    let mut rotated = *faces;
+    for (id, face) in Faces::ALL.iter().enumerate() {
+        let normal = inverse([face.normal.0, face.normal.1, face.normal.2]);
+        let source = Faces::ALL
+            .iter()
+            .position(|f| [f.normal.0, f.normal.1, f.normal.2] == normal)
+            .unwrap();
+
+        for (c, point) in face.points.iter().enumerate() {
+            // Rotate around the block's center, using doubled coordinates to stay on integers
+            let centered = inverse([point.0 * 2 - 1, point.1 * 2 - 1, point.2 * 2 - 1]);
+            let point = (
+                (centered[0] + 1) / 2,
+                (centered[1] + 1) / 2,
+                (centered[2] + 1) / 2,
+            );
+            let corner = Faces::ALL[source]
+                .points
+                .iter()
+                .position(|p| *p == point)
+                .unwrap();
+            rotated[id].uvs[c] = faces[source].uvs[corner];
+        }
+        rotated[id].layer = faces[source].layer;
+    }
+    rotated
+}
+
+/// Matrix of an (x, y) rotation in degrees, where `x` is applied first
+pub(super) fn rotation_matrix((x, y): (i32, i32)) -> Matrix {
+    let turn = |degrees: i32| {
+        if degrees % 90 != 0 {
+            warn!(
+                "Block rotations must be multiples of 90 degrees, got {}",
+                degrees
+            );
+        }
+        [(1, 0), (0, 1), (-1, 0), (0, -1)][(degrees / 90).rem_euclid(4) as usize]
+    };
+    let (cos, sin) = turn(x);
+    let rotate_x: Matrix = [[1, 0, 0], [0, cos, -sin], [0, sin, cos]];
+    let (cos, sin) = turn(y);
+    let rotate_y: Matrix = [[cos, 0, sin], [0, 1, 0], [-sin, 0, cos]];
+    multiply(&rotate_y, &rotate_x)
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0; 3]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::{Block, Registry};
+
+    use super::{create_texture_map, rotate_faces, FaceTexture, FaceTextures, MISSING_LAYER};
+
+    #[test]
+    fn test_shipped_packs_load() {
+        // Packs are found relative to the workspace root, where the game runs from
+        std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../..")).unwrap();
+        let mut registry = Registry::default();
+        let (_, info, errors) = create_texture_map("ghibli", &mut registry);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // Stone is only defined by the classic pack, which ghibli inherits from
+        let stone = Block::new(registry.get_block_id("stone").unwrap());
+        assert!(info.faces[&stone]
+            .iter()
+            .all(|face| face.layer != MISSING_LAYER));
+        assert!(registry.get_block_id("red_leaves").is_some());
+    }
+
+    #[test]
+    fn test_rotate_faces() {
+        // Mark every corner with its face id and corner index
+        let mut faces: FaceTextures = [FaceTexture::default(); 6];
+        for (id, face) in faces.iter_mut().enumerate() {
+            face.layer = id as u32;
+            for (c, corner) in face.uvs.iter_mut().enumerate() {
+                *corner = [id as f32, c as f32];
+            }
+        }
+        assert_eq!(rotate_faces(&faces, (0, 0)), faces);
+
+        // A log lying along x shows its top and bottom textures on the right and left faces
+        let rotated = rotate_faces(&faces, (90, 90));
+        for id in [0, 1] {
+            assert!(rotated[id]
+                .uvs
+                .iter()
+                .all(|corner| corner[0] == 2.0 || corner[0] == 3.0));
+        }
+        for id in [2, 3, 4, 5] {
+            assert!(rotated[id]
+                .uvs
+                .iter()
+                .all(|corner| corner[0] != 2.0 && corner[0] != 3.0));
+        }
+
+        // Every face still uses all four corners of its source face, and its layer
+        for face in rotated {
+            assert!(face.uvs.iter().all(|corner| corner[0] == face.layer as f32));
+            let mut corners: Vec<f32> = face.uvs.iter().map(|corner| corner[1]).collect();
+            corners.sort_by(f32::total_cmp);
+            assert_eq!(corners, [0.0, 1.0, 2.0, 3.0]);
+        }
+    }
+}
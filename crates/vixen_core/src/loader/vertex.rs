@@ -0,0 +1,149 @@
+use bevy::{
+    math::Vec3,
+    render::{mesh::MeshVertexAttribute, render_resource::VertexFormat},
+};
+
+use super::chunk::Faces;
+
+/// Chunk mesh vertex attribute holding a `PackedVertex`, decoded by the voxel shader
+pub const ATTRIBUTE_PACKED_VERTEX: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Packed", 281_964_530, VertexFormat::Uint32x2);
+
+/// Number of layers of the block texture array that vertices can address
+pub(super) const MAX_LAYERS: u32 = 1 << 8;
+
+/// Number of steps positions and texture coordinates are stored in per block
+const UNITS: f32 = 16.0;
+
+/// A chunk mesh vertex packed into two words, laid out as
+///
+/// ```text
+/// word 0: x (10 bits) | y (10 bits) | z (10 bits) | ambient occlusion (2 bits)
+/// word 1: u (6 bits) | v (6 bits) | layer (8 bits) | normal (3 bits) | light (6 bits)
+/// ```
+///
+/// Positions are in sixteenths of a block from the chunk's corner, which is enough for model
+/// quads. Texture coordinates within the layer are in sixteenths up to 1, and in whole textures
+/// above that, where they only run on faces merged by greedy meshing. The normal is the id of the
+/// face it points along, and quads that do not face along an axis, like the crossed planes of
+/// plants, are lit as if they faced up. Light is in quarter levels, so smoothed lighting keeps its
+/// steps. The top 3 bits of word 1 are unused.
+/// Keep in sync with `voxel.wgsl`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PackedVertex(pub [u32; 2]);
+
+impl PackedVertex {
+    pub fn new(
+        position: [f32; 3],
+        face_id: usize,
+        (uv, layer): ([f32; 2], u32),
+        ao: u8,
+        light: u8,
+    ) -> Self {
+        let [x, y, z] = position.map(|value| to_units(value) & 0x3ff);
+        let [u, v] = uv.map(pack_tex_coord);
+        PackedVertex([
+            x | y << 10 | z << 20 | (ao as u32 & 0x3) << 30,
+            u | v << 6
+                | (layer & 0xff) << 12
+                | (face_id as u32 & 0x7) << 20
+                | (light as u32 & 0x3f) << 23,
+        ])
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        [0, 10, 20].map(|shift| ((self.0[0] >> shift) & 0x3ff) as f32 / UNITS)
+    }
+
+    pub fn face_id(&self) -> usize {
+        ((self.0[1] >> 20) & 0x7) as usize
+    }
+
+    pub fn uv(&self) -> [f32; 2] {
+        [0, 6].map(|shift| unpack_tex_coord((self.0[1] >> shift) & 0x3f))
+    }
+
+    pub fn layer(&self) -> u32 {
+        (self.0[1] >> 12) & 0xff
+    }
+
+    pub fn ao(&self) -> u8 {
+        (self.0[0] >> 30) as u8
+    }
+
+    pub fn light(&self) -> u8 {
+        ((self.0[1] >> 23) & 0x3f) as u8
+    }
+}
+
+impl From<[u32; 2]> for PackedVertex {
+    fn from(words: [u32; 2]) -> Self {
+        PackedVertex(words)
+    }
+}
+
+impl From<PackedVertex> for Vec3 {
+    fn from(vertex: PackedVertex) -> Self {
+        Vec3::from(vertex.position())
+    }
+}
+
+/// Id of the face a normal points along, or of the top face if it points along none of them
+pub(super) fn facing(normal: [f32; 3]) -> usize {
+    Faces::ALL
+        .iter()
+        .position(|face| {
+            [face.normal.0, face.normal.1, face.normal.2].map(|value| value as f32) == normal
+        })
+        .unwrap_or(Faces::TOP.face_id as usize)
+}
+
+#[inline]
+fn to_units(value: f32) -> u32 {
+    (value * UNITS).round().max(0.0) as u32
+}
+
+/// Packs a texture coordinate into 6 bits, as sixteenths up to 1 followed by whole textures up to
+/// 48
+#[inline]
+fn pack_tex_coord(value: f32) -> u32 {
+    if value <= 1.0 {
+        to_units(value)
+    } else {
+        (value.round() as u32 + UNITS as u32 - 1).min(0x3f)
+    }
+}
+
+#[inline]
+fn unpack_tex_coord(packed: u32) -> f32 {
+    if packed <= UNITS as u32 {
+        packed as f32 / UNITS
+    } else {
+        (packed + 1 - UNITS as u32) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{facing, PackedVertex};
+
+    #[test]
+    fn test_packed_vertex_round_trip() {
+        let vertex = PackedVertex::new([32.0, 0.5, 17.0625], 4, ([0.4375, 1.0], 255), 2, 60);
+        assert_eq!(vertex.position(), [32.0, 0.5, 17.0625]);
+        assert_eq!(vertex.face_id(), 4);
+        assert_eq!(vertex.uv(), [0.4375, 1.0]);
+        assert_eq!(vertex.layer(), 255);
+        assert_eq!(vertex.ao(), 2);
+        assert_eq!(vertex.light(), 60);
+
+        // Merged faces repeat whole textures along both axes
+        let merged = PackedVertex::new([0.0; 3], 0, ([32.0, 2.0], 0), 3, 0);
+        assert_eq!(merged.uv(), [32.0, 2.0]);
+        assert_eq!(merged.ao(), 3);
+
+        assert_eq!(facing([0.0, -1.0, 0.0]), 2);
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(facing([diagonal, 0.0, -diagonal]), 3);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::ivec3,
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunk::Faces,
+    palette::{linear_index, VOLUME},
+    BlockPropertyTable, ChunkCoord, ChunkData, ChunkMap, CHUNK_SIZE,
+};
+
+/// Which faces of a chunk can see each other through the blocks inside it, as a row of bits for
+/// every face in the order of `Faces::ALL`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkConnectivity([u8; 6]);
+
+impl ChunkConnectivity {
+    /// Every face sees every other face, like an empty chunk
+    pub const ALL: ChunkConnectivity = ChunkConnectivity([0b111111; 6]);
+    /// No face sees any other face, like a chunk of solid stone
+    pub const NONE: ChunkConnectivity = ChunkConnectivity([0; 6]);
+
+    /// Flood fills the blocks that do not stop light to find which faces of the chunk connect
+    pub fn compute(data: &ChunkData, properties: &BlockPropertyTable) -> ChunkConnectivity {
+        let Some(data) = data else {
+            return ChunkConnectivity::ALL;
+        };
+        if let Some(block) = data.get_uniform() {
+            return if properties.get(block).is_opaque() {
+                ChunkConnectivity::NONE
+            } else {
+                ChunkConnectivity::ALL
+            };
+        }
+
+        let size = [CHUNK_SIZE.0, CHUNK_SIZE.1, CHUNK_SIZE.2];
+        let mut connectivity = ChunkConnectivity::NONE;
+        let mut visited = vec![false; VOLUME];
+        let mut stack = Vec::new();
+        for i in 0..size[0] {
+            for j in 0..size[1] {
+                for k in 0..size[2] {
+                    let index = linear_index(i, j, k);
+                    if visited[index] {
+                        continue;
+                    }
+                    visited[index] = true;
+                    if properties.get(data.get((i, j, k))).is_opaque() {
+                        continue;
+                    }
+
+                    // Faces reached by this pocket of open blocks
+                    let mut faces = 0u8;
+                    stack.push([i, j, k]);
+                    while let Some(pos) = stack.pop() {
+                        for (id, face) in Faces::ALL.iter().enumerate() {
+                            let (n, _, _) = face.axes;
+                            let normal = [face.normal.0, face.normal.1, face.normal.2];
+                            let next = pos[n] as i32 + normal[n];
+                            if next < 0 || next >= size[n] as i32 {
+                                faces |= 1 << id;
+                                continue;
+                            }
+
+                            let mut next_pos = pos;
+                            next_pos[n] = next as usize;
+                            let [x, y, z] = next_pos;
+                            let index = linear_index(x, y, z);
+                            if !visited[index] {
+                                visited[index] = true;
+                                if !properties.get(data.get((x, y, z))).is_opaque() {
+                                    stack.push(next_pos);
+                                }
+                            }
+                        }
+                    }
+
+                    for (id, row) in connectivity.0.iter_mut().enumerate() {
+                        if faces & (1 << id) != 0 {
+                            *row |= faces;
+                        }
+                    }
+                    if connectivity == ChunkConnectivity::ALL {
+                        return connectivity;
+                    }
+                }
+            }
+        }
+        connectivity
+    }
+
+    /// Whether something entering through face `from` can leave through face `to`
+    #[inline]
+    pub fn connects(&self, from: usize, to: usize) -> bool {
+        self.0[from] & (1 << to) != 0
+    }
+}
+
+/// Returns the chunks the camera can see into, walking outwards from the camera's chunk and only
+/// passing through a chunk between faces that connect. Chunks that are not loaded are treated as
+/// empty, and `in_range` limits how far the walk goes.
+pub(super) fn visible_chunks(
+    camera: ChunkCoord,
+    chunk_map: &ChunkMap,
+    in_range: impl Fn(&ChunkCoord) -> bool,
+) -> HashSet<ChunkCoord> {
+    // Every chunk reached, with the faces it was entered through
+    let mut visited = HashMap::new();
+    let mut queue = VecDeque::new();
+    visited.insert(camera, 0u8);
+    queue.push_back((camera, None));
+
+    while let Some((coord, from)) = queue.pop_front() {
+        let connectivity = chunk_map
+            .get(&coord)
+            .map_or(ChunkConnectivity::ALL, |chunk| chunk.get_connectivity());
+
+        for (id, face) in Faces::ALL.iter().enumerate() {
+            let direction = ivec3(face.normal.0, face.normal.1, face.normal.2);
+            let neighbor = coord + direction;
+            // Only walk away from the camera, so that paths cannot double back into view
+            if (neighbor - camera).dot(direction) <= 0 || !in_range(&neighbor) {
+                continue;
+            }
+            if matches!(from, Some(from) if !connectivity.connects(from, id)) {
+                continue;
+            }
+
+            // Faces come in pairs in `Faces::ALL`, so the neighbor is entered through the other one
+            let entry = id ^ 1;
+            let entered = visited.entry(neighbor).or_insert(0);
+            if *entered & (1 << entry) == 0 {
+                *entered |= 1 << entry;
+                queue.push_back((neighbor, Some(entry)));
+            }
+        }
+    }
+    visited.into_keys().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::{Block, BlockPropertyTable, BlockStorage, CHUNK_SIZE};
+
+    use super::ChunkConnectivity;
+
+    #[test]
+    fn test_connectivity() {
+        let properties = BlockPropertyTable::default();
+        assert_eq!(
+            ChunkConnectivity::compute(&None, &properties),
+            ChunkConnectivity::ALL
+        );
+
+        // A solid floor splits the chunk into an upper and a lower pocket
+        let mut data = BlockStorage::default();
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                data.set((i, 16, k), Block::new(1));
+            }
+        }
+        let connectivity = ChunkConnectivity::compute(&Some(data), &properties);
+        assert!(!connectivity.connects(2, 3));
+        assert!(connectivity.connects(2, 0));
+        assert!(connectivity.connects(3, 4));
+        assert!(connectivity.connects(0, 5));
+    }
+}
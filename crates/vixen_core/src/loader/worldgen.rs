@@ -1,33 +1,88 @@
-use std::sync::Arc;
+use std::{io, sync::Arc};
 
-use crate::{loader::*, terrain::TerrainGenerator, util::BlockCoord};
-use bevy::{math::ivec3, utils::HashMap};
+use crate::{
+    loader::*,
+    terrain::{TerrainGenerator, TerrainSettings},
+    util::{to_chunk_coord, to_world_coord, BlockCoord},
+};
+use bevy::{
+    math::ivec3,
+    render::mesh::{Indices, VertexAttributeValues},
+    utils::HashMap,
+};
 use dashmap::DashMap;
-use ndarray::Array3;
 
-use super::texture::{TextureMapHandle, TextureMapInfo};
+use super::{
+    fluid::{flow, FluidQueue},
+    light::{light_new_chunk, update_light},
+    lod::MeshDetail,
+    neighborhood::{ChunkNeighborhood, Neighbors},
+    texture::TextureMapInfo,
+    visibility::visible_chunks,
+};
 
 pub type ChunkMap = HashMap<ChunkCoord, Chunk>;
 
+/// Chunks farther than this from the camera keep the face order they were meshed with
+const TRANSPARENT_SORT_DISTANCE: i32 = 2;
+
+/// Entities and mesh assets spawned for a chunk
+#[derive(Default)]
+struct ChunkMeshes {
+    entities: Vec<Entity>,
+    meshes: Vec<Handle<Mesh>>,
+    /// Mesh with alpha blended faces, which has to be re-sorted as the camera moves
+    transparent: Option<Handle<Mesh>>,
+    detail: MeshDetail,
+}
+
+impl ChunkMeshes {
+    fn despawn(self, meshes: &mut Assets<Mesh>, commands: &mut Commands) {
+        for entity in self.entities {
+            commands.entity(entity).despawn();
+        }
+        for mesh in self.meshes {
+            meshes.remove(mesh);
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct Worldgen {
     chunk_map: ChunkMap,
-    mesh_map: HashMap<ChunkCoord, Handle<Mesh>>,
+    mesh_map: HashMap<ChunkCoord, ChunkMeshes>,
     generator: Arc<TerrainGenerator>,
     needs_mesh_build: HashSet<ChunkCoord>,
     needs_chunk_build: HashSet<ChunkCoord>,
     in_progress: Arc<DashMap<ChunkCoord, UnfinishedChunkData>>,
+    save: Arc<WorldSave>,
+    registry: Arc<Registry>,
+    properties: Arc<BlockPropertyTable>,
+    /// Set when chunks or their meshes changed since the last visibility update
+    visibility_dirty: bool,
+    fluids: FluidQueue,
 }
 
 impl Worldgen {
-    pub fn new(seed: u32) -> Self {
+    /// Creates the world generator, with `registry` holding the blocks of the world's data pack
+    pub fn new(
+        seed: u32,
+        settings: TerrainSettings,
+        save: Arc<WorldSave>,
+        registry: Arc<Registry>,
+    ) -> Self {
         Self {
-            generator: Arc::new(TerrainGenerator::new(seed)),
+            generator: Arc::new(TerrainGenerator::new(seed, settings, registry.clone())),
+            save,
+            properties: Arc::new(BlockPropertyTable::new(&registry)),
+            registry,
             chunk_map: Default::default(),
             mesh_map: Default::default(),
             needs_mesh_build: Default::default(),
             needs_chunk_build: Default::default(),
             in_progress: Arc::new(DashMap::new()),
+            visibility_dirty: true,
+            fluids: Default::default(),
         }
     }
 
@@ -41,6 +96,9 @@ impl Worldgen {
                     self.needs_chunk_build.insert(chunk_coord);
                     let generator = self.generator.clone();
                     let in_progress = self.in_progress.clone();
+                    let save = self.save.clone();
+                    let registry = self.registry.clone();
+                    let properties = self.properties.clone();
 
                     let mut loaded = 0u32;
                     let mut c = 0;
@@ -56,7 +114,22 @@ impl Worldgen {
                     }
 
                     let task = pool.spawn(async move {
-                        generator.generate_chunk(loaded, chunk_coord, in_progress)
+                        // Chunks changed by the player come from the save, everything else is
+                        // regenerated
+                        let (coord, mut chunk) = match save.load_chunk(chunk_coord, &registry) {
+                            Some(data) => {
+                                in_progress.remove(&chunk_coord);
+                                let chunk = match data.get_uniform() {
+                                    Some(block) if block.is_air() => Chunk::empty(chunk_coord),
+                                    _ => Chunk::from_data(chunk_coord, data),
+                                };
+                                (chunk_coord, chunk)
+                            }
+                            None => generator.generate_chunk(loaded, chunk_coord, in_progress),
+                        };
+                        chunk.update_connectivity(&properties);
+                        chunk.find_fluid_edges(&properties);
+                        (coord, chunk)
                     });
                     commands.spawn(ChunkBuildTask(task));
                 }
@@ -64,19 +137,81 @@ impl Worldgen {
         }
     }
 
-    pub fn build_chunk(&mut self, chunk_coord: ChunkCoord, chunk: Chunk) {
+    pub fn build_chunk(&mut self, chunk_coord: ChunkCoord, mut chunk: Chunk) {
+        let origin = chunk_coord
+            * ivec3(
+                CHUNK_SIZE.0 as i32,
+                CHUNK_SIZE.1 as i32,
+                CHUNK_SIZE.2 as i32,
+            );
+        for (i, j, k) in chunk.take_fluid_edges() {
+            self.fluids
+                .push(origin + ivec3(i as i32, j as i32, k as i32));
+        }
         self.chunk_map.insert(chunk_coord, chunk);
         self.needs_mesh_build.insert(chunk_coord);
         self.needs_chunk_build.remove(&chunk_coord);
+        self.visibility_dirty = true;
+        self.queue_border_fluids(chunk_coord);
+
+        let changed = light_new_chunk(&mut self.chunk_map, &self.properties, chunk_coord);
+        self.request_updates(changed);
+    }
+
+    /// Queues the air on either side of a new chunk's border with its loaded neighbors that fluid
+    /// on the other side can flow into, like a cave running under the sea of the chunk next to it
+    fn queue_border_fluids(&mut self, coord: ChunkCoord) {
+        let size = [CHUNK_SIZE.0, CHUNK_SIZE.1, CHUNK_SIZE.2].map(|size| size as i32);
+        let origin = coord * ivec3(size[0], size[1], size[2]);
+        let is_fluid =
+            |block: Option<Block>| matches!(block, Some(block) if self.properties.get(block).fluid);
+        let is_air = |block: Option<Block>| matches!(block, Some(block) if block.is_air());
+
+        let mut edges = Vec::new();
+        for face in Faces::ALL {
+            let normal = [face.normal.0, face.normal.1, face.normal.2];
+            if !self
+                .chunk_map
+                .contains_key(&(coord + ivec3(normal[0], normal[1], normal[2])))
+            {
+                continue;
+            }
+            let (n, u, v) = face.axes;
+            for a in 0..size[u] {
+                for b in 0..size[v] {
+                    let mut local = [0; 3];
+                    local[n] = if normal[n] > 0 { size[n] - 1 } else { 0 };
+                    local[u] = a;
+                    local[v] = b;
+                    let inside = origin + ivec3(local[0], local[1], local[2]);
+                    let outside = inside + ivec3(normal[0], normal[1], normal[2]);
+                    let (inside_block, outside_block) =
+                        (self.get_block(&inside), self.get_block(&outside));
+                    if is_air(inside_block) && is_fluid(outside_block) {
+                        edges.push(inside);
+                    } else if is_air(outside_block) && is_fluid(inside_block) {
+                        edges.push(outside);
+                    }
+                }
+            }
+        }
+        for pos in edges {
+            self.fluids.push(pos);
+        }
     }
 
     pub fn queue_mesh_rebuild(&mut self, scanner: Query<&ChunkScanner>) {
         for (coord, chunk) in self.chunk_map.iter() {
-            if chunk.needs_update()
-                || (!chunk.is_empty()
-                    && scanner.single().should_load_mesh(coord)
-                    && !self.mesh_map.contains_key(coord))
-            {
+            // Meshes are rebuilt when the camera moves them to another level of detail
+            let outdated = match (
+                self.mesh_map.get(coord),
+                MeshDetail::new(scanner.single(), *coord),
+            ) {
+                (Some(chunk_meshes), Some(detail)) => chunk_meshes.detail != detail,
+                (None, Some(_)) => true,
+                (_, None) => false,
+            };
+            if chunk.needs_update() || (!chunk.is_empty() && outdated) {
                 self.needs_mesh_build.insert(*coord);
             }
         }
@@ -86,11 +221,14 @@ impl Worldgen {
         &mut self,
         scanner: Query<&ChunkScanner>,
         mut meshes: ResMut<Assets<Mesh>>,
-        mut materials: ResMut<Assets<StandardMaterial>>,
         mut commands: Commands,
-        texture_map: Res<TextureMapHandle>,
+        materials: Res<ChunkMaterials>,
         texture_map_info: Res<TextureMapInfo>,
+        meshing_mode: Res<MeshingMode>,
     ) {
+        let meshing_mode = *meshing_mode;
+        let properties = &*self.properties;
+        let eye = scanner.single().get_center().as_vec3() + 0.5;
         let pool = AsyncComputeTaskPool::get();
         let task = pool.scope(|scope| {
             self.needs_mesh_build.drain_filter(|coord| {
@@ -98,21 +236,32 @@ impl Worldgen {
                     if chunk.is_empty() {
                         return true;
                     }
-                    if scanner.single().should_load_mesh(coord) {
-                        if let Some(neighbors) = get_neighbors_data(&self.chunk_map, *coord) {
-                            let info = &texture_map_info.0;
-                            let data = chunk.get_data().as_ref().unwrap();
+                    if let Some(detail) = MeshDetail::new(scanner.single(), *coord) {
+                        if let Some(mut neighbors) = get_neighbors(&self.chunk_map, *coord) {
+                            for (face, seam) in Faces::ALL.iter().zip(detail.seams) {
+                                if seam {
+                                    let (x, y, z) = face.normal;
+                                    neighbors[(x + 1) as usize][(y + 1) as usize]
+                                        [(z + 1) as usize] = None;
+                                }
+                            }
+                            let info = &texture_map_info.faces;
+                            let hood = ChunkNeighborhood::new(
+                                chunk.get_data().as_ref().unwrap(),
+                                chunk.get_light(),
+                                neighbors,
+                                properties,
+                                &texture_map_info.models,
+                            );
                             let coord = *coord;
                             scope.spawn(async move {
-                                let (positions, normals, uvs, indices) =
-                                    Chunk::gen_mesh(data, neighbors, info);
-                                let mut mesh =
-                                    Mesh::new(bevy::render::mesh::PrimitiveTopology::TriangleList);
-                                mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-                                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-                                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                                mesh.set_indices(Some(bevy::render::mesh::Indices::U32(indices)));
-                                (coord, mesh)
+                                let mut mesh = match (detail.level, meshing_mode) {
+                                    (0, MeshingMode::Naive) => Chunk::gen_mesh(&hood, info),
+                                    (0, MeshingMode::Greedy) => Chunk::gen_mesh_greedy(&hood, info),
+                                    (level, _) => Chunk::gen_mesh_lod(&hood, info, level),
+                                };
+                                mesh.transparent.sort_quads(eye - to_world_coord(&coord));
+                                (coord, detail, mesh)
                             });
 
                             return true;
@@ -123,39 +272,113 @@ impl Worldgen {
             });
         });
 
-        for (coord, mesh) in task {
-            if let Some(mesh_handle) = self.mesh_map.get(&coord) {
-                meshes.remove(mesh_handle);
+        for (coord, detail, mesh) in task {
+            if let Some(old) = self.mesh_map.remove(&coord) {
+                old.despawn(&mut meshes, &mut commands);
             }
-            let mesh_handle = meshes.add(mesh);
-            self.mesh_map.insert(coord, mesh_handle.clone());
-
-            commands.spawn(MaterialMeshBundle {
-                mesh: mesh_handle,
-                material: materials.add(StandardMaterial {
-                    base_color_texture: Some(texture_map.0.clone()),
-                    reflectance: 0.0,
-                    metallic: 0.0,
-                    perceptual_roughness: 1.0,
-                    ..default()
-                }),
-                transform: Transform::from_xyz(
-                    coord.x as f32 * CHUNK_SIZE.0 as f32,
-                    coord.y as f32 * CHUNK_SIZE.1 as f32,
-                    coord.z as f32 * CHUNK_SIZE.2 as f32,
-                ),
+
+            let mut chunk_meshes = ChunkMeshes {
+                detail,
                 ..default()
-            });
+            };
+            for (mesh, material, transparent) in [
+                (mesh.opaque, &materials.opaque, false),
+                (mesh.cutout, &materials.cutout, false),
+                (mesh.transparent, &materials.transparent, true),
+            ] {
+                if mesh.is_empty() {
+                    continue;
+                }
+                let mesh_handle = meshes.add(mesh.into_mesh());
+                if transparent {
+                    chunk_meshes.transparent = Some(mesh_handle.clone());
+                }
+                chunk_meshes.meshes.push(mesh_handle.clone());
+                chunk_meshes.entities.push(
+                    commands
+                        .spawn(MaterialMeshBundle {
+                            mesh: mesh_handle,
+                            material: material.clone(),
+                            transform: Transform::from_translation(to_world_coord(&coord)),
+                            ..default()
+                        })
+                        .id(),
+                );
+            }
+            self.mesh_map.insert(coord, chunk_meshes);
+            self.visibility_dirty = true;
 
             self.chunk_map.get_mut(&coord).unwrap().set_updated();
         }
     }
 
+    /// Re-sorts the alpha blended faces of chunks near `eye`, which is in world space
+    pub fn sort_transparent_faces(&self, eye: Vec3, mut meshes: ResMut<Assets<Mesh>>) {
+        let center = to_chunk_coord(&eye);
+        for (coord, chunk_meshes) in self.mesh_map.iter() {
+            let Some(handle) = chunk_meshes.transparent.as_ref() else {
+                continue;
+            };
+            if (*coord - center).abs().max_element() > TRANSPARENT_SORT_DISTANCE {
+                continue;
+            }
+            let Some(mesh) = meshes.get_mut(handle) else {
+                continue;
+            };
+            let Some(VertexAttributeValues::Uint32x2(vertices)) =
+                mesh.attribute(ATTRIBUTE_PACKED_VERTEX)
+            else {
+                continue;
+            };
+            let vertices: Vec<PackedVertex> =
+                vertices.iter().copied().map(PackedVertex::from).collect();
+            if let Some(Indices::U32(indices)) = mesh.indices_mut() {
+                sort_quads(&vertices, indices, eye - to_world_coord(coord));
+            }
+        }
+    }
+
+    /// Hides the meshes of chunks that the camera cannot see into from `camera`, its chunk, such as
+    /// caves enclosed by solid rock
+    pub fn update_visibility(
+        &mut self,
+        camera: ChunkCoord,
+        scanner: Query<&ChunkScanner>,
+        mut visibilities: Query<&mut Visibility>,
+    ) {
+        let scanner = scanner.single();
+        let visible = visible_chunks(camera, &self.chunk_map, |coord| {
+            scanner.should_load_mesh(coord)
+        });
+
+        // Entities spawned this frame cannot be queried yet, so try again next frame
+        self.visibility_dirty = false;
+        for (coord, chunk_meshes) in self.mesh_map.iter() {
+            let is_visible = visible.contains(coord);
+            for entity in chunk_meshes.entities.iter() {
+                match visibilities.get_mut(*entity) {
+                    Ok(mut visibility) if visibility.is_visible != is_visible => {
+                        visibility.is_visible = is_visible;
+                    }
+                    Ok(_) => (),
+                    Err(_) => self.visibility_dirty = true,
+                }
+            }
+        }
+    }
+
+    pub fn needs_visibility_update(&self) -> bool {
+        self.visibility_dirty
+    }
+
     pub fn unload_chunks(&mut self, scanner: Query<&ChunkScanner>) {
-        self.chunk_map.drain_filter(|coord, _chunk| {
-            scanner.into_iter().fold(true, |unload, scanner| {
+        let (save, registry) = (&self.save, &self.registry);
+        self.chunk_map.retain(|coord, chunk| {
+            let unload = scanner.into_iter().fold(true, |unload, scanner| {
                 unload && scanner.should_unload_chunk(coord)
-            })
+            });
+            // Chunks that fail to save stay loaded, so their changes are not lost
+            !(unload && save_modified_chunk(save, registry, *coord, chunk))
         });
 
         self.in_progress.retain(|coord, _| {
@@ -169,12 +392,13 @@ impl Worldgen {
         &mut self,
         scanner: Query<&ChunkScanner>,
         mut meshes: ResMut<Assets<Mesh>>,
+        mut commands: Commands,
     ) {
         self.mesh_map
             .drain_filter(|coord, _mesh| !scanner.single().should_load_mesh(coord))
             .into_iter()
-            .for_each(|(_, mesh)| {
-                meshes.remove(mesh);
+            .for_each(|(_, chunk_meshes)| {
+                chunk_meshes.despawn(&mut meshes, &mut commands);
             });
     }
 
@@ -206,74 +430,142 @@ impl Worldgen {
                 error!("Tried to set block in unloaded chunk: {:?}", chunk_coord);
             }
             Some(chunk) => {
-                if chunk.set_block(
-                    (
-                        (x - chunk_coord.x * CHUNK_SIZE.0 as i32) as usize,
-                        (y - chunk_coord.y * CHUNK_SIZE.1 as i32) as usize,
-                        (z - chunk_coord.z * CHUNK_SIZE.2 as i32) as usize,
-                    ),
-                    block,
-                ) {
-                    self.update_neighbors(chunk_coord);
+                let local = (
+                    (x - chunk_coord.x * CHUNK_SIZE.0 as i32) as usize,
+                    (y - chunk_coord.y * CHUNK_SIZE.1 as i32) as usize,
+                    (z - chunk_coord.z * CHUNK_SIZE.2 as i32) as usize,
+                );
+                let old = chunk.get_block(local).unwrap_or_else(Block::air);
+                if chunk.set_block(local, block) {
+                    chunk.update_connectivity(&self.properties);
+                    self.visibility_dirty = true;
+                    self.update_neighbors(chunk_coord, local);
+
+                    let properties = &self.properties;
+                    let changed = update_light(&mut self.chunk_map, properties, *coord, old, block);
+                    self.request_updates(changed);
+                    self.fluids.block_changed(*coord);
+                }
+            }
+        }
+    }
+
+    /// Requests a mesh rebuild for the neighbors of a chunk that touch the block at `local`, whose
+    /// faces, ambient occlusion or smooth light it can change
+    fn update_neighbors(&mut self, coord: ChunkCoord, local: (usize, usize, usize)) {
+        let offsets = |value: usize, size: usize| {
+            let below = if value == 0 { -1 } else { 0 };
+            let above = if value == size - 1 { 1 } else { 0 };
+            below..=above
+        };
+        for x in offsets(local.0, CHUNK_SIZE.0) {
+            for y in offsets(local.1, CHUNK_SIZE.1) {
+                for z in offsets(local.2, CHUNK_SIZE.2) {
+                    if (x, y, z) == (0, 0, 0) {
+                        continue;
+                    }
+                    if let Some(chunk) = self.chunk_map.get_mut(&(coord + ivec3(x, y, z))) {
+                        chunk.request_update();
+                    }
                 }
             }
         }
     }
 
-    fn update_neighbors(&mut self, coord: ChunkCoord) {
-        let neighbors = [
-            ivec3(1, 0, 0) + coord,
-            ivec3(-1, 0, 0) + coord,
-            ivec3(0, -1, 0) + coord,
-            ivec3(0, 1, 0) + coord,
-            ivec3(0, 0, 1) + coord,
-            ivec3(0, 0, -1) + coord,
-        ];
-        neighbors.into_iter().for_each(|coord| {
-            if let Some(chunk) = self.chunk_map.get_mut(&coord) {
-                chunk.request_update();
+    /// Requests a mesh rebuild for every chunk in `coords` that has blocks to mesh
+    fn request_updates(&mut self, coords: HashSet<ChunkCoord>) {
+        for coord in coords {
+            match self.chunk_map.get_mut(&coord) {
+                Some(chunk) if !chunk.is_empty() => chunk.request_update(),
+                _ => (),
             }
-        });
+        }
+    }
+
+    /// Requests a mesh rebuild for every loaded chunk, after the block textures or models changed
+    pub fn remesh_all(&mut self) {
+        let coords = self.chunk_map.keys().copied().collect();
+        self.request_updates(coords);
+    }
+
+    /// Makes the world open with `data_pack` from now on
+    pub fn save_data_pack(&self, data_pack: &str) -> io::Result<()> {
+        match self.save.load_level() {
+            Some(level) => self.save.save_level(&LevelInfo {
+                data_pack: data_pack.to_owned(),
+                ..level
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes every modified chunk that is still loaded to the world save. Chunks that fail to save
+    /// stay modified so the next save tries them again, and false is returned.
+    pub fn save_all(&mut self) -> bool {
+        let mut saved = true;
+        for (coord, chunk) in self.chunk_map.iter_mut() {
+            if save_modified_chunk(&self.save, &self.registry, *coord, chunk) {
+                chunk.set_saved();
+            } else {
+                saved = false;
+            }
+        }
+        saved
+    }
+
+    /// Lets fluid flow at up to `limit` of the blocks queued since blocks around them changed
+    pub fn update_fluids(&mut self, limit: usize) {
+        for pos in self.fluids.take(limit) {
+            if let Some(block) = flow(pos, |pos| self.get_block(&pos), &self.properties) {
+                self.set_block(&pos, block);
+            }
+        }
     }
 
     pub fn loaded_chunk_count(&self) -> usize {
         self.chunk_map.len()
     }
+
+    /// Blocks and biomes of this world, including the blocks of its data pack
+    pub fn registry(&self) -> &Arc<Registry> {
+        &self.registry
+    }
 }
 
-fn get_neighbors_data(
-    chunk_map: &HashMap<ChunkCoord, Chunk>,
-    coord: IVec3,
-) -> Option<[Option<&Box<Array3<Block>>>; 6]> {
-    chunk_map
-        .get(&(ivec3(1, 0, 0) + coord))
-        .map(|chunk| chunk.get_data().as_ref())
-        .and_then(|x| {
-            chunk_map
-                .get(&(ivec3(-1, 0, 0) + coord))
-                .map(|chunk| chunk.get_data().as_ref())
-                .and_then(|y| {
-                    chunk_map
-                        .get(&(ivec3(0, -1, 0) + coord))
-                        .map(|chunk| chunk.get_data().as_ref())
-                        .and_then(|z| {
-                            chunk_map
-                                .get(&(ivec3(0, 1, 0) + coord))
-                                .map(|chunk| chunk.get_data().as_ref())
-                                .and_then(|w| {
-                                    chunk_map
-                                        .get(&(ivec3(0, 0, 1) + coord))
-                                        .map(|chunk| chunk.get_data().as_ref())
-                                        .and_then(|u| {
-                                            chunk_map
-                                                .get(&(ivec3(0, 0, -1) + coord))
-                                                .map(|chunk| chunk.get_data().as_ref())
-                                                .map(|v| [x, y, z, w, u, v])
-                                        })
-                                })
-                        })
-                })
-        })
+/// Writes a chunk to the world save if it was modified, and returns whether it is saved
+fn save_modified_chunk(
+    save: &WorldSave,
+    registry: &Registry,
+    coord: ChunkCoord,
+    chunk: &Chunk,
+) -> bool {
+    // Modified chunks always have block data, since `Chunk::set_block` creates it
+    let (true, Some(data)) = (chunk.is_modified(), chunk.get_data()) else {
+        return true;
+    };
+    match save.save_chunk(coord, data, registry) {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Failed to save chunk {}: {}", coord, e);
+            false
+        }
+    }
+}
+
+/// Returns the 26 chunks around a chunk, or None if any of them is not loaded
+fn get_neighbors(chunk_map: &ChunkMap, coord: ChunkCoord) -> Option<Neighbors<'_>> {
+    let mut neighbors: Neighbors = Default::default();
+    for (x, plane) in neighbors.iter_mut().enumerate() {
+        for (y, row) in plane.iter_mut().enumerate() {
+            for (z, neighbor) in row.iter_mut().enumerate() {
+                let offset = ivec3(x as i32 - 1, y as i32 - 1, z as i32 - 1);
+                if offset != IVec3::ZERO {
+                    *neighbor = Some(chunk_map.get(&(coord + offset))?);
+                }
+            }
+        }
+    }
+    Some(neighbors)
 }
 
 #[derive(Debug)]
@@ -7,7 +7,7 @@ use bevy::{
 use bevy_atmosphere::prelude::AtmosphereCamera;
 
 use crate::{
-    loader::{get_block, Block, ChunkScanner, Worldgen},
+    loader::{Block, ChunkScanner, Worldgen},
     physics::{Movement, SweptCollider, AABB},
     storage::StorageContainer,
     util::BlockCoord,
@@ -78,6 +78,9 @@ impl Default for Miner {
 #[derive(Component)]
 struct Builder(Instant);
 
+/// Code name of the block the player builds with
+const BUILD_BLOCK: &str = "stone";
+
 impl Miner {
     pub fn mine(&mut self, coord: &BlockCoord, delta: f32, speed: f32, worldgen: &mut Worldgen) {
         if &self.coord != coord {
@@ -86,9 +89,11 @@ impl Miner {
         self.coord = *coord;
         self.update();
         let block = worldgen.get_block(coord).unwrap_or_else(Block::air);
-        let health = get_block(block.id).unwrap().get_durability();
+        let registry = worldgen.registry();
+        let health = registry.get_block(block.id).unwrap().get_durability();
+        let targetable = registry.get_block_properties(block).is_targetable();
         self.mining_progress += delta * speed;
-        if health - self.mining_progress <= 0.0 && !block.is_air() {
+        if health - self.mining_progress <= 0.0 && targetable {
             worldgen.set_block(coord, Block::air());
         }
     }
@@ -203,7 +208,9 @@ fn build(
                         translation.z.floor() as i32,
                     )
             {
-                worldgen.set_block(&coord, Block::new(1));
+                if let Some(id) = worldgen.registry().get_block_id(BUILD_BLOCK) {
+                    worldgen.set_block(&coord, Block::new(id));
+                }
             }
         }
     }
@@ -223,7 +230,11 @@ fn cast_ray(start_point: Vec3, rho: f32, forward: Vec3, loader: &Worldgen) -> Bl
         &line_drawing::VoxelOrigin::Corner,
     ) {
         if let Some(block) = loader.get_block(&ivec3(x, y, z)) {
-            if !block.is_air() {
+            if loader
+                .registry()
+                .get_block_properties(block)
+                .is_targetable()
+            {
                 return ivec3(x, y, z);
             }
         }
@@ -235,7 +246,8 @@ fn cast_ray(start_point: Vec3, rho: f32, forward: Vec3, loader: &Worldgen) -> Bl
     )
 }
 
-/// Casts a ray and returns block coordinate of the air block in front of the block the ray hit, and None otherwise
+/// Casts a ray and returns block coordinate of the block in front of the block the ray hit, and
+/// None otherwise
 fn cast_ray_in_front(
     start_point: Vec3,
     rho: f32,
@@ -260,7 +272,11 @@ fn cast_ray_in_front(
     ) {
         let coord = ivec3(x, y, z);
         if let Some(block) = loader.get_block(&coord) {
-            if block.id != 0 {
+            if loader
+                .registry()
+                .get_block_properties(block)
+                .is_targetable()
+            {
                 return Some(last);
             }
         }
@@ -375,10 +391,8 @@ fn get_nearby_blocks<'a>(
             (y_min..y_max).flat_map(move |y| (z_min..z_max).map(move |z| ivec3(x, y, z)))
         })
         .filter(move |coord| {
-            !worldgen
-                .get_block(coord)
-                .unwrap_or_else(Block::air)
-                .is_air()
+            let block = worldgen.get_block(coord).unwrap_or_else(Block::air);
+            worldgen.registry().get_block_properties(block).solid
         })
 }
 
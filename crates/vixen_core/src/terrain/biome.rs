@@ -3,25 +3,78 @@ use std::sync::Arc;
 use dashmap::DashMap;
 
 use crate::{
-    loader::{ChunkData, UnfinishedChunkData},
+    loader::{ChunkData, UnfinishedChunkData, CHUNK_SIZE},
     util::ChunkCoord,
     BlockCoord,
 };
 
+use super::{ChunkTerrain, ClimateRange, TerrainShape};
+
+/// Block columns of a chunk, indexed by their local x and z
+pub type ColumnMask = [[bool; CHUNK_SIZE.2]; CHUNK_SIZE.0];
+
 pub trait Biome: Sync + Send {
     fn get_name(&self) -> &'static str;
+    /// Climate the biome generates in. Columns whose climate is in no biome's range get the
+    /// biome with the nearest one.
+    fn get_climate(&self) -> ClimateRange;
+    /// Shape of the biome's terrain, which is blended with its neighbors' near its borders
+    fn get_terrain_shape(&self) -> TerrainShape;
     fn generate_structures(
         &self,
         block_coord: &BlockCoord,
         in_progress: Arc<DashMap<ChunkCoord, UnfinishedChunkData>>,
         rng: &mut rand::rngs::StdRng,
     );
+    /// Fills the block columns of the chunk at `coord` that are set in `columns`, where this biome
+    /// is dominant, following the blended `terrain`. Caves are carved and structures placed
+    /// afterwards.
     fn generate_chunk(
         &self,
         coord: ChunkCoord,
         chunk_data: &mut ChunkData,
-        in_progress: Arc<DashMap<ChunkCoord, UnfinishedChunkData>>,
-        noise: &Box<dyn noise::NoiseFn<f64, 3> + Send + Sync>,
+        columns: &ColumnMask,
+        terrain: &ChunkTerrain,
         seed: u32,
     );
 }
+
+/// Biome of every block column in a chunk, by biome id
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BiomeMap([[u16; CHUNK_SIZE.2]; CHUNK_SIZE.0]);
+
+impl BiomeMap {
+    pub fn new(biome: impl Fn(usize, usize) -> u16) -> Self {
+        let mut map = [[0; CHUNK_SIZE.2]; CHUNK_SIZE.0];
+        for (i, row) in map.iter_mut().enumerate() {
+            for (k, id) in row.iter_mut().enumerate() {
+                *id = biome(i, k);
+            }
+        }
+        Self(map)
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize, k: usize) -> u16 {
+        self.0[i][k]
+    }
+
+    /// Ids of the biomes in the chunk, in ascending order
+    pub fn biomes(&self) -> Vec<u16> {
+        let mut biomes: Vec<u16> = self.0.iter().flatten().copied().collect();
+        biomes.sort_unstable();
+        biomes.dedup();
+        biomes
+    }
+
+    /// Columns that have the biome `id`
+    pub fn columns(&self, id: u16) -> ColumnMask {
+        let mut columns = [[false; CHUNK_SIZE.2]; CHUNK_SIZE.0];
+        for (i, row) in columns.iter_mut().enumerate() {
+            for (k, column) in row.iter_mut().enumerate() {
+                *column = self.0[i][k] == id;
+            }
+        }
+        columns
+    }
+}
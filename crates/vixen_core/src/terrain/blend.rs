@@ -0,0 +1,225 @@
+use noise::NoiseFn;
+
+use crate::{loader::CHUNK_SIZE, util::BlockCoord};
+
+use super::BiomeMap;
+
+/// Distance in blocks between the points biomes are sampled at for blending. Every chunk samples
+/// the same points, so blended terrain lines up across chunk borders.
+const BLEND_STEP: i32 = 4;
+
+/// Blocks per unit of the 3D noise that makes terrain rough
+const DENSITY_SCALE: f64 = 24.0;
+
+/// Shape of a biome's terrain. Near biome borders the shapes of the biomes around a column are
+/// blended, so the terrain changes smoothly from one biome to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TerrainShape {
+    /// Height the terrain varies around
+    pub base_height: f64,
+    /// How far the terrain rises above and sinks below `base_height`
+    pub height_variation: f64,
+    /// Blocks of 3D noise added to the terrain's density, which carves overhangs when high
+    pub roughness: f64,
+}
+
+impl TerrainShape {
+    /// Height of the terrain where the height noise is `noise`
+    pub fn height(&self, noise: f64) -> f64 {
+        self.base_height + self.height_variation * noise
+    }
+
+    fn weighted(&self, weight: f64) -> TerrainShape {
+        TerrainShape {
+            base_height: self.base_height * weight,
+            height_variation: self.height_variation * weight,
+            roughness: self.roughness * weight,
+        }
+    }
+
+    fn add(&self, other: &TerrainShape) -> TerrainShape {
+        TerrainShape {
+            base_height: self.base_height + other.base_height,
+            height_variation: self.height_variation + other.height_variation,
+            roughness: self.roughness + other.roughness,
+        }
+    }
+}
+
+/// Blended terrain of every block column in a chunk, which biomes fill with their blocks
+pub struct ChunkTerrain<'a> {
+    origin: BlockCoord,
+    heights: [[f64; CHUNK_SIZE.2]; CHUNK_SIZE.0],
+    roughness: [[f64; CHUNK_SIZE.2]; CHUNK_SIZE.0],
+    density_noise: &'a (dyn NoiseFn<f64, 3> + Send + Sync),
+}
+
+impl<'a> ChunkTerrain<'a> {
+    /// Creates the terrain of the chunk starting at block `origin` from the blended shape of each
+    /// column and the height noise at its world x and z
+    pub fn new(
+        origin: BlockCoord,
+        shapes: &[[TerrainShape; CHUNK_SIZE.2]; CHUNK_SIZE.0],
+        height_noise: impl Fn(i32, i32) -> f64,
+        density_noise: &'a (dyn NoiseFn<f64, 3> + Send + Sync),
+    ) -> Self {
+        let mut heights = [[0.0; CHUNK_SIZE.2]; CHUNK_SIZE.0];
+        let mut roughness = [[0.0; CHUNK_SIZE.2]; CHUNK_SIZE.0];
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                let shape = &shapes[i][k];
+                heights[i][k] =
+                    shape.height(height_noise(origin.x + i as i32, origin.z + k as i32));
+                roughness[i][k] = shape.roughness;
+            }
+        }
+        Self {
+            origin,
+            heights,
+            roughness,
+            density_noise,
+        }
+    }
+
+    /// Height of the terrain surface in the column at local `i` and `k`, without roughness
+    #[inline]
+    pub fn height(&self, i: usize, k: usize) -> i32 {
+        self.heights[i][k] as i32
+    }
+
+    /// Density of the terrain at local `i` and `k` and world height `y`. It is the depth below the
+    /// surface, moved up or down by rough terrain.
+    pub fn density(&self, i: usize, y: i32, k: usize) -> f64 {
+        let depth = (self.height(i, k) - y) as f64;
+        let roughness = self.roughness[i][k];
+        if roughness == 0.0 {
+            return depth;
+        }
+        let point = [
+            (self.origin.x + i as i32) as f64 / DENSITY_SCALE,
+            y as f64 / DENSITY_SCALE,
+            (self.origin.z + k as i32) as f64 / DENSITY_SCALE,
+        ];
+        depth + roughness * self.density_noise.get(point)
+    }
+
+    /// Whether the terrain at local `i` and `k` and world height `y` is solid ground
+    #[inline]
+    pub fn is_solid(&self, i: usize, y: i32, k: usize) -> bool {
+        self.density(i, y, k) > 0.0
+    }
+}
+
+/// Blends the terrain shapes of the biomes within `radius` blocks of every column in the chunk
+/// starting at `origin`. `biome_at` picks the biome at a world x and z, and `shapes` holds the
+/// shape of every biome by id.
+///
+/// Returns the dominant biome of each column, which has the most weight in its blend, along with
+/// the blended shapes.
+pub fn blend_biomes(
+    origin: BlockCoord,
+    radius: u32,
+    biome_at: impl Fn(i32, i32) -> u16,
+    shapes: &[TerrainShape],
+) -> (BiomeMap, [[TerrainShape; CHUNK_SIZE.2]; CHUNK_SIZE.0]) {
+    let shape_of = |id: u16| shapes.get(id as usize).copied().unwrap_or_default();
+    let mut blended = [[TerrainShape::default(); CHUNK_SIZE.2]; CHUNK_SIZE.0];
+
+    if radius == 0 {
+        let biome_map = BiomeMap::new(|i, k| biome_at(origin.x + i as i32, origin.z + k as i32));
+        for (i, row) in blended.iter_mut().enumerate() {
+            for (k, shape) in row.iter_mut().enumerate() {
+                *shape = shape_of(biome_map.get(i, k));
+            }
+        }
+        return (biome_map, blended);
+    }
+
+    // Every column has a sample point within half a step diagonal, so the radius has to reach it
+    let radius = (radius as i32).max(BLEND_STEP);
+    let floor = |value: i32| value.div_euclid(BLEND_STEP);
+    let ceil = |value: i32| -(-value).div_euclid(BLEND_STEP);
+
+    // Biome at every sample point the chunk's columns can reach
+    let (min_x, min_z) = (ceil(origin.x - radius), ceil(origin.z - radius));
+    let max_x = floor(origin.x + CHUNK_SIZE.0 as i32 - 1 + radius);
+    let max_z = floor(origin.z + CHUNK_SIZE.2 as i32 - 1 + radius);
+    let width = (max_z - min_z + 1) as usize;
+    let samples: Vec<u16> = (min_x..=max_x)
+        .flat_map(|gx| (min_z..=max_z).map(move |gz| (gx, gz)))
+        .map(|(gx, gz)| biome_at(gx * BLEND_STEP, gz * BLEND_STEP))
+        .collect();
+
+    let mut dominant = [[0; CHUNK_SIZE.2]; CHUNK_SIZE.0];
+    let mut weights: Vec<(u16, f64)> = Vec::new();
+    for i in 0..CHUNK_SIZE.0 {
+        for k in 0..CHUNK_SIZE.2 {
+            let (x, z) = (origin.x + i as i32, origin.z + k as i32);
+            weights.clear();
+            let mut shape = TerrainShape::default();
+            let mut total = 0.0;
+            for gx in ceil(x - radius)..=floor(x + radius) {
+                for gz in ceil(z - radius)..=floor(z + radius) {
+                    let (dx, dz) = (gx * BLEND_STEP - x, gz * BLEND_STEP - z);
+                    let distance = (dx * dx + dz * dz) as f64 / (radius * radius) as f64;
+                    if distance >= 1.0 {
+                        continue;
+                    }
+                    // Points fade out smoothly towards the edge of the radius
+                    let weight = (1.0 - distance).powi(2);
+                    let id = samples[(gx - min_x) as usize * width + (gz - min_z) as usize];
+                    match weights.iter_mut().find(|(other, _)| *other == id) {
+                        Some((_, sum)) => *sum += weight,
+                        None => weights.push((id, weight)),
+                    }
+                    shape = shape.add(&shape_of(id).weighted(weight));
+                    total += weight;
+                }
+            }
+
+            blended[i][k] = shape.weighted(1.0 / total);
+            dominant[i][k] = weights
+                .iter()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(0, |(id, _)| *id);
+        }
+    }
+
+    (BiomeMap::new(|i, k| dominant[i][k]), blended)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec3;
+
+    use super::{blend_biomes, TerrainShape};
+    use crate::loader::CHUNK_SIZE;
+
+    #[test]
+    fn test_blend_biome_border() {
+        let shapes = [
+            TerrainShape {
+                base_height: 0.0,
+                ..Default::default()
+            },
+            TerrainShape {
+                base_height: 40.0,
+                ..Default::default()
+            },
+        ];
+        // The biome border runs through the middle of the chunk
+        let origin = ivec3(-(CHUNK_SIZE.0 as i32) / 2, 0, 0);
+        let biome_at = |x: i32, _z: i32| if x < 0 { 0 } else { 1 };
+        let steepest = |radius| {
+            let (biome_map, shapes) = blend_biomes(origin, radius, biome_at, &shapes);
+            assert_eq!(biome_map.get(0, 0), 0);
+            assert_eq!(biome_map.get(CHUNK_SIZE.0 - 1, 0), 1);
+            (1..CHUNK_SIZE.0)
+                .map(|i| shapes[i][0].base_height - shapes[i - 1][0].base_height)
+                .fold(0.0, f64::max)
+        };
+
+        assert_eq!(steepest(0), 40.0);
+        assert!(steepest(12) < 10.0);
+    }
+}
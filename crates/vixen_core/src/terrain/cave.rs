@@ -0,0 +1,139 @@
+use noise::{NoiseFn, Perlin};
+
+use crate::loader::{Block, ChunkData, CHUNK_SIZE};
+use crate::util::ChunkCoord;
+
+use super::{CaveSettings, ChunkTerrain};
+
+/// Blocks per unit of the noise that carves cheese caverns, across and upwards. Caverns are
+/// squashed so they are wider than they are tall.
+const CHEESE_SCALE: (f64, f64) = (48.0, 24.0);
+
+/// Blocks per unit of the noise that carves spaghetti tunnels
+const SPAGHETTI_SCALE: f64 = 64.0;
+
+/// Blocks per unit of the noise that places cave entrances
+const ENTRANCE_SCALE: f64 = 96.0;
+
+/// Carves caves out of generated terrain with 3D noise.
+///
+/// Cheese caverns are carved where one noise is high, and spaghetti tunnels where two noises are
+/// both near zero, which happens along winding lines. Caves stay `surface_depth` blocks below the
+/// surface, except that tunnels break through where the entrance noise is high.
+pub struct CaveCarver {
+    settings: CaveSettings,
+    cheese: Perlin,
+    spaghetti: (Perlin, Perlin),
+    entrances: Perlin,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u32, settings: CaveSettings) -> Self {
+        Self {
+            settings,
+            cheese: Perlin::new(seed.wrapping_add(30)),
+            spaghetti: (
+                Perlin::new(seed.wrapping_add(31)),
+                Perlin::new(seed.wrapping_add(32)),
+            ),
+            entrances: Perlin::new(seed.wrapping_add(33)),
+        }
+    }
+
+    /// Whether the block at world `x`, `y` and `z`, which is `depth` blocks below the surface, is
+    /// carved out
+    pub fn is_cave(&self, x: i32, y: i32, z: i32, depth: i32) -> bool {
+        if !self.settings.enabled || depth <= 0 {
+            return false;
+        }
+        let (x, y, z) = (x as f64, y as f64, z as f64);
+
+        let near_surface = depth < self.settings.surface_depth;
+        if !near_surface {
+            let (across, up) = CHEESE_SCALE;
+            if self.cheese.get([x / across, y / up, z / across]) > self.settings.cheese_threshold {
+                return true;
+            }
+        }
+
+        if near_surface {
+            let entrance = self.entrances.get([x / ENTRANCE_SCALE, z / ENTRANCE_SCALE]);
+            if entrance <= self.settings.entrance_threshold {
+                return false;
+            }
+        }
+        let point = [
+            x / SPAGHETTI_SCALE,
+            y / SPAGHETTI_SCALE,
+            z / SPAGHETTI_SCALE,
+        ];
+        let width = self.settings.spaghetti_width;
+        self.spaghetti.0.get(point).abs() < width && self.spaghetti.1.get(point).abs() < width
+    }
+
+    /// Replaces the carved blocks of the chunk at `coord` with air
+    pub fn carve(&self, coord: ChunkCoord, chunk_data: &mut ChunkData, terrain: &ChunkTerrain) {
+        if !self.settings.enabled {
+            return;
+        }
+        let Some(data) = chunk_data.as_mut() else {
+            return;
+        };
+        let origin = coord
+            * ChunkCoord::new(
+                CHUNK_SIZE.0 as i32,
+                CHUNK_SIZE.1 as i32,
+                CHUNK_SIZE.2 as i32,
+            );
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                let surface = terrain.height(i, k);
+                for j in 0..CHUNK_SIZE.1 {
+                    let y = origin.y + j as i32;
+                    if data.get((i, j, k)).is_air() {
+                        continue;
+                    }
+                    let (x, z) = (origin.x + i as i32, origin.z + k as i32);
+                    if self.is_cave(x, y, z, surface - y) {
+                        data.set((i, j, k), Block::air());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CaveCarver;
+    use crate::terrain::CaveSettings;
+
+    #[test]
+    fn test_caves_stay_underground() {
+        let settings = CaveSettings {
+            cheese_threshold: 0.0,
+            entrance_threshold: 1.0,
+            ..Default::default()
+        };
+        let carver = CaveCarver::new(0, settings.clone());
+        let carved = |depth: i32| {
+            (0..64)
+                .flat_map(|x| (0..64).map(move |z| (x * 3, z * 3)))
+                .filter(|&(x, z)| carver.is_cave(x, -depth, z, depth))
+                .count()
+        };
+        // Without entrances, nothing is carved near the surface
+        assert_eq!(carved(1), 0);
+        assert_eq!(carved(settings.surface_depth - 1), 0);
+        assert!(carved(settings.surface_depth + 10) > 0);
+
+        let disabled = CaveCarver::new(
+            0,
+            CaveSettings {
+                enabled: false,
+                ..settings
+            },
+        );
+        assert!(!disabled.is_cave(7, -40, 7, 40));
+    }
+}
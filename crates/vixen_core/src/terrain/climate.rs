@@ -0,0 +1,134 @@
+use std::ops::Range;
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+/// Frequency of temperature changes, in cycles per block
+const TEMPERATURE_FREQUENCY: f64 = 1.0 / 1024.0;
+
+/// Frequency of humidity changes, in cycles per block
+const HUMIDITY_FREQUENCY: f64 = 1.0 / 768.0;
+
+/// Frequency of continentalness changes, in cycles per block. Lower than the others so that
+/// continents are larger than the climate zones on them.
+const CONTINENTALNESS_FREQUENCY: f64 = 1.0 / 2048.0;
+
+/// Climate of a block column. Every parameter is roughly between -1.0 and 1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Climate {
+    pub temperature: f64,
+    pub humidity: f64,
+    /// How far inland the column is, from deep ocean to the middle of a continent
+    pub continentalness: f64,
+}
+
+/// Climate a biome generates in
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClimateRange {
+    pub temperature: Range<f64>,
+    pub humidity: Range<f64>,
+    pub continentalness: Range<f64>,
+}
+
+impl ClimateRange {
+    /// Squared distance from `climate` to the nearest climate in the range, which is zero for
+    /// climates inside it
+    pub fn distance_squared(&self, climate: &Climate) -> f64 {
+        let outside =
+            |range: &Range<f64>, value: f64| (range.start - value).max(value - range.end).max(0.0);
+        outside(&self.temperature, climate.temperature).powi(2)
+            + outside(&self.humidity, climate.humidity).powi(2)
+            + outside(&self.continentalness, climate.continentalness).powi(2)
+    }
+
+    /// Squared distance from `climate` to the center of the range
+    pub fn center_distance_squared(&self, climate: &Climate) -> f64 {
+        let center = |range: &Range<f64>| (range.start + range.end) / 2.0;
+        (center(&self.temperature) - climate.temperature).powi(2)
+            + (center(&self.humidity) - climate.humidity).powi(2)
+            + (center(&self.continentalness) - climate.continentalness).powi(2)
+    }
+}
+
+/// Picks the biome whose climate range is nearest to `climate`. When several ranges contain it,
+/// the one whose center is nearest wins.
+pub fn nearest_biome<'a>(
+    climate: &Climate,
+    ranges: impl IntoIterator<Item = (u16, &'a ClimateRange)>,
+) -> Option<u16> {
+    ranges
+        .into_iter()
+        .map(|(id, range)| {
+            let distance = (
+                range.distance_squared(climate),
+                range.center_distance_squared(climate),
+            );
+            (id, distance)
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(id, _)| id)
+}
+
+/// Samples the climate of block columns from noise seeded by the world seed
+pub struct ClimateSampler {
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    continentalness: Fbm<Perlin>,
+}
+
+impl ClimateSampler {
+    pub fn new(seed: u32) -> Self {
+        let fbm = |seed: u32, frequency: f64| {
+            Fbm::<Perlin>::new(seed)
+                .set_frequency(frequency)
+                .set_persistence(0.5)
+                .set_octaves(4)
+        };
+        Self {
+            temperature: fbm(seed.wrapping_add(10), TEMPERATURE_FREQUENCY),
+            humidity: fbm(seed.wrapping_add(11), HUMIDITY_FREQUENCY),
+            continentalness: fbm(seed.wrapping_add(12), CONTINENTALNESS_FREQUENCY),
+        }
+    }
+
+    /// Climate of the block column at world `x` and `z`
+    pub fn sample(&self, x: i32, z: i32) -> Climate {
+        let point = [x as f64, z as f64];
+        Climate {
+            temperature: self.temperature.get(point),
+            humidity: self.humidity.get(point),
+            continentalness: self.continentalness.get(point),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_biome, Climate, ClimateRange};
+
+    #[test]
+    fn test_nearest_biome() {
+        let cold = ClimateRange {
+            temperature: -1.0..-0.2,
+            humidity: -1.0..1.0,
+            continentalness: -1.0..1.0,
+        };
+        let hot = ClimateRange {
+            temperature: 0.2..1.0,
+            humidity: -1.0..0.0,
+            continentalness: -1.0..1.0,
+        };
+        let ranges = [(0, &cold), (1, &hot)];
+        let climate = |temperature, humidity| Climate {
+            temperature,
+            humidity,
+            continentalness: 0.0,
+        };
+
+        // Negative climates select biomes like any other
+        assert_eq!(nearest_biome(&climate(-0.6, 0.0), ranges), Some(0));
+        assert_eq!(nearest_biome(&climate(0.6, -0.5), ranges), Some(1));
+        // Climates outside every range go to the nearest one
+        assert_eq!(nearest_biome(&climate(0.15, 0.0), ranges), Some(1));
+        assert_eq!(nearest_biome(&climate(0.6, 0.3), ranges), Some(1));
+    }
+}
@@ -1,31 +1,74 @@
-use std::sync::Arc;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
 
 use dashmap::DashMap;
 use noise::{NoiseFn, Perlin};
+use rand::SeedableRng;
+
+use crate::loader::{
+    Block, BlockStorage, Chunk, ChunkData, Registry, UnfinishedChunkData, CHUNK_SIZE, SOURCE_LEVEL,
+};
+use crate::util::{block_to_chunk_coord, block_to_chunk_local_coord, BlockCoord, ChunkCoord};
 
-use crate::loader::{Block, Chunk, ChunkData, UnfinishedChunkData, CHUNK_SIZE, BIOMES};
-use crate::{
-    loader::get_biome,
-    util::{block_to_chunk_coord, block_to_chunk_local_coord, BlockCoord, ChunkCoord},
+use super::{
+    blend::{blend_biomes, ChunkTerrain, TerrainShape},
+    cave::CaveCarver,
+    climate::{nearest_biome, ClimateSampler},
+    ore::generate_veins,
+    simple_noise::simple_noise,
+    BiomeMap, ClimateRange, TerrainSettings,
 };
 
-use super::simple_noise::simple_noise;
+/// Blocks per unit of the height noise
+const HEIGHT_SCALE: f64 = 3200.0;
 
 pub struct TerrainGenerator {
     seed: u32,
+    settings: TerrainSettings,
+    registry: Arc<Registry>,
     noise: Box<dyn NoiseFn<f64, 3> + Send + Sync>,
-    biome_noise: Box<dyn NoiseFn<f64, 3> + Send + Sync>,
+    density_noise: Box<dyn NoiseFn<f64, 3> + Send + Sync>,
+    climate: ClimateSampler,
+    carver: CaveCarver,
+    /// Climate range of every registered biome, by biome id
+    climate_ranges: Vec<ClimateRange>,
+    /// Terrain shape of every registered biome, by biome id
+    shapes: Vec<TerrainShape>,
+    /// Source block of the fluid seas are filled with
+    sea_block: Option<Block>,
 }
 
 impl TerrainGenerator {
-    /// Create a new Terrain Generator with a non-negative seed
-    pub fn new(seed: u32) -> TerrainGenerator {
+    /// Create a new Terrain Generator with a non-negative seed, generating the biomes of `registry`
+    pub fn new(seed: u32, settings: TerrainSettings, registry: Arc<Registry>) -> TerrainGenerator {
         let noise = simple_noise(seed);
-        let biome_noise = Perlin::new(seed);
+        let density_noise = Perlin::new(seed.wrapping_add(20));
+        let climate_ranges = registry
+            .biomes()
+            .map(|(_, biome)| biome.get_climate())
+            .collect();
+        let shapes = registry
+            .biomes()
+            .map(|(_, biome)| biome.get_terrain_shape())
+            .collect();
+        let sea_block = registry
+            .get_block_id(&settings.sea_block)
+            .map(|id| Block::with_state(id, SOURCE_LEVEL));
+        let carver = CaveCarver::new(seed, settings.caves.clone());
         TerrainGenerator {
             seed,
+            settings,
+            registry,
             noise: Box::new(noise),
-            biome_noise: Box::new(biome_noise),
+            density_noise: Box::new(density_noise),
+            climate: ClimateSampler::new(seed),
+            carver,
+            climate_ranges,
+            shapes,
+            sea_block,
         }
     }
 
@@ -94,10 +137,9 @@ impl TerrainGenerator {
         overlapped_blocks
             .into_iter()
             .for_each(|((x, y, z), block)| {
-                if chunk_data.is_none() {
-                    chunk_data = Some(Box::new(ndarray::Array3::default(CHUNK_SIZE)));
-                }
-                chunk_data.as_mut().unwrap()[(x, y, z)] = block;
+                chunk_data
+                    .get_or_insert_with(BlockStorage::default)
+                    .set((x, y, z), block);
             });
 
         let chunk = match chunk_data {
@@ -126,14 +168,26 @@ impl TerrainGenerator {
         }
         drop(entry);
 
-        let biome: u16 = pick_biome(coord, &self.biome_noise);
-        get_biome(biome).unwrap().generate_chunk(
-            coord,
-            &mut chunk_data,
-            in_progress.clone(),
-            &self.noise,
-            self.seed,
-        );
+        // Biomes fill in the terrain, caves are carved out of it and ores placed in what is left,
+        // and then seas fill up to sea level and structures are placed on the ground
+        let (biome_map, terrain) = self.chunk_terrain(coord);
+        for id in biome_map.biomes() {
+            if let Some(biome) = self.registry.get_biome(id) {
+                biome.generate_chunk(
+                    coord,
+                    &mut chunk_data,
+                    &biome_map.columns(id),
+                    &terrain,
+                    self.seed,
+                );
+            }
+        }
+        self.carver.carve(coord, &mut chunk_data, &terrain);
+        for (id, ore) in self.registry.ores() {
+            generate_veins(ore, id, coord, &mut chunk_data, self.seed);
+        }
+        self.fill_sea(coord, &mut chunk_data, &terrain);
+        self.place_structures(coord, &biome_map, &terrain, in_progress.clone());
 
         let mut entry = in_progress.entry(coord).or_insert(UnfinishedChunkData {
             data: None,
@@ -145,6 +199,102 @@ impl TerrainGenerator {
         entry.finished = true;
     }
 
+    /// Fills the air above the terrain and below sea level with sea fluid. Caves the sea borders
+    /// are left as air, and flood once the chunk is loaded and its fluid edges are queued.
+    fn fill_sea(&self, coord: ChunkCoord, chunk_data: &mut ChunkData, terrain: &ChunkTerrain) {
+        let Some(sea_block) = self.sea_block else {
+            return;
+        };
+        let bottom = coord.y * CHUNK_SIZE.1 as i32;
+        let sea_level = self.settings.sea_level;
+        if bottom >= sea_level {
+            return;
+        }
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                let surface = terrain.height(i, k).max(bottom);
+                for y in surface..sea_level.min(bottom + CHUNK_SIZE.1 as i32) {
+                    let local = (i, (y - bottom) as usize, k);
+                    if get_block_from_chunk(chunk_data, local)
+                        .unwrap_or_else(Block::air)
+                        .is_air()
+                    {
+                        set_block_in_chunk(chunk_data, local, sea_block);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lets the dominant biome of every column place structures on its surface, if the surface is
+    /// in the chunk at `coord`, above the sea and on ground that was not carved out
+    fn place_structures(
+        &self,
+        coord: ChunkCoord,
+        biome_map: &BiomeMap,
+        terrain: &ChunkTerrain,
+        in_progress: Arc<DashMap<ChunkCoord, UnfinishedChunkData>>,
+    ) {
+        let origin = coord
+            * ChunkCoord::new(
+                CHUNK_SIZE.0 as i32,
+                CHUNK_SIZE.1 as i32,
+                CHUNK_SIZE.2 as i32,
+            );
+        for i in 0..CHUNK_SIZE.0 {
+            for k in 0..CHUNK_SIZE.2 {
+                let surface = terrain.height(i, k);
+                let j = surface - origin.y;
+                if !(0..CHUNK_SIZE.1 as i32).contains(&j)
+                    || terrain.is_solid(i, surface, k)
+                    || (self.sea_block.is_some() && surface < self.settings.sea_level)
+                {
+                    continue;
+                }
+                let (x, z) = (origin.x + i as i32, origin.z + k as i32);
+                if self.carver.is_cave(x, surface - 1, z, 1) {
+                    continue;
+                }
+                let Some(biome) = self.registry.get_biome(biome_map.get(i, k)) else {
+                    continue;
+                };
+
+                let j = j as usize;
+                let mut hasher = DefaultHasher::new();
+                (self.seed, coord, i, j, k).hash(&mut hasher);
+                let mut rand = rand::rngs::StdRng::seed_from_u64(hasher.finish());
+                let block_coord = BlockCoord::new(x, surface, z);
+                biome.generate_structures(&block_coord, in_progress.clone(), &mut rand);
+            }
+        }
+    }
+
+    /// Picks the biome of the block column at world `x` and `z` from its climate
+    pub fn biome_at(&self, x: i32, z: i32) -> u16 {
+        let climate = self.climate.sample(x, z);
+        let ranges = (0..).zip(self.climate_ranges.iter());
+        // Without any biome registered, chunks are left empty
+        nearest_biome(&climate, ranges).unwrap_or(0)
+    }
+
+    /// Returns the dominant biome of every block column in the chunk at `coord`, and its terrain
+    /// blended from the biomes around each column
+    pub fn chunk_terrain(&self, coord: ChunkCoord) -> (BiomeMap, ChunkTerrain<'_>) {
+        let origin = coord * ChunkCoord::new(CHUNK_SIZE.0 as i32, 0, CHUNK_SIZE.2 as i32);
+        let (biome_map, shapes) = blend_biomes(
+            origin,
+            self.settings.blend_radius,
+            |x, z| self.biome_at(x, z),
+            &self.shapes,
+        );
+        let height_noise = |x: i32, z: i32| {
+            self.noise
+                .get([x as f64 / HEIGHT_SCALE, z as f64 / HEIGHT_SCALE, 0.0])
+        };
+        let terrain = ChunkTerrain::new(origin, &shapes, height_noise, &*self.density_noise);
+        (biome_map, terrain)
+    }
+
     /// Returns world seed
     pub fn get_seed(&self) -> u32 {
         self.seed
@@ -152,20 +302,13 @@ impl TerrainGenerator {
 }
 
 pub fn get_block_from_chunk(chunk_data: &ChunkData, coord: (usize, usize, usize)) -> Option<Block> {
-    let Some(chunk_data) = chunk_data else {
-        return None;
-    };
-
-    Some(chunk_data[(coord.0, coord.1, coord.2)].clone())
+    chunk_data.as_ref().map(|data| data.get(coord))
 }
 
 pub fn set_block_in_chunk(chunk_data: &mut ChunkData, coord: (usize, usize, usize), block: Block) {
-    if let Some(chunk_data) = chunk_data {
-        chunk_data[(coord.0, coord.1, coord.2)] = block;
-    } else {
-        *chunk_data = Some(Box::new(ndarray::Array3::default(CHUNK_SIZE)));
-        chunk_data.as_deref_mut().unwrap()[(coord.0, coord.1, coord.2)] = block;
-    }
+    chunk_data
+        .get_or_insert_with(BlockStorage::default)
+        .set(coord, block);
 }
 
 pub fn set_block_in_neighborhood(
@@ -186,13 +329,3 @@ pub fn set_block_in_neighborhood(
         });
     entry.block_list.push((local_coord, block));
 }
-
-fn pick_biome(coord: ChunkCoord, biome_noise: &impl NoiseFn<f64, 3>) -> u16 {
-    let x = coord.x as f64;
-    let y = coord.y as f64;
-    let z = coord.z as f64;
-
-    let noise = biome_noise.get([x, y, z]) * BIOMES.len() as f64;
-    
-    noise.floor() as u16
-}
@@ -1,10 +1,20 @@
 mod biome;
+mod blend;
+mod cave;
+mod climate;
 mod generator;
+mod ore;
+mod settings;
 mod simple_noise;
 mod structure;
 // mod complex_noise;
 
-pub use biome::Biome;
+pub use biome::{Biome, BiomeMap, ColumnMask};
+pub use blend::{ChunkTerrain, TerrainShape};
+pub use cave::CaveCarver;
+pub use climate::{nearest_biome, Climate, ClimateRange, ClimateSampler};
 pub use generator::TerrainGenerator;
 pub use generator::{get_block_from_chunk, set_block_in_chunk, set_block_in_neighborhood};
+pub use ore::{generate_veins, Ore};
+pub use settings::{CaveSettings, TerrainSettings};
 pub use structure::Structure;
@@ -0,0 +1,152 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+
+use bevy::math::ivec3;
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    loader::{Block, ChunkData, CHUNK_SIZE},
+    util::ChunkCoord,
+};
+
+/// Ore that generates in veins inside other blocks
+pub trait Ore: Send + Sync {
+    fn get_name(&self) -> &'static str;
+    fn get_block(&self) -> Block;
+    /// World heights that veins start at
+    fn get_heights(&self) -> Range<i32>;
+    /// Number of steps a vein takes, placing ore at every block it reaches
+    fn get_vein_size(&self) -> u32;
+    /// Number of veins started in every chunk that overlaps `get_heights`
+    fn get_attempts(&self) -> u32;
+    /// Whether a vein can replace `block`, such as the stone it generates in
+    fn can_replace(&self, block: Block) -> bool;
+}
+
+/// Places the veins of `ore`, registered with `id`, in the chunk at `coord`. Veins stay inside the
+/// chunk, and are the same every time the chunk is generated with `seed`.
+pub fn generate_veins(
+    ore: &dyn Ore,
+    id: u16,
+    coord: ChunkCoord,
+    chunk_data: &mut ChunkData,
+    seed: u32,
+) {
+    let Some(data) = chunk_data.as_mut() else {
+        return;
+    };
+    let bottom = coord.y * CHUNK_SIZE.1 as i32;
+    let heights = ore.get_heights();
+    let (low, high) = (
+        heights.start.max(bottom),
+        heights.end.min(bottom + CHUNK_SIZE.1 as i32),
+    );
+    if low >= high {
+        return;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    (seed, coord, id).hash(&mut hasher);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(hasher.finish());
+
+    let size = ivec3(
+        CHUNK_SIZE.0 as i32,
+        CHUNK_SIZE.1 as i32,
+        CHUNK_SIZE.2 as i32,
+    );
+    let block = ore.get_block();
+    for _ in 0..ore.get_attempts() {
+        let mut pos = ivec3(
+            rng.gen_range(0..size.x),
+            rng.gen_range(low..high) - bottom,
+            rng.gen_range(0..size.z),
+        );
+        for _ in 0..ore.get_vein_size() {
+            let local = (pos.x as usize, pos.y as usize, pos.z as usize);
+            if ore.can_replace(data.get(local)) {
+                data.set(local, block);
+            }
+            // Wander to a neighboring block, staying in the chunk
+            let axis = rng.gen_range(0..3);
+            let step = if rng.gen() { 1 } else { -1 };
+            pos[axis] = (pos[axis] + step).clamp(0, size[axis] - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use bevy::math::ivec3;
+
+    use super::{generate_veins, Ore};
+    use crate::loader::{Block, BlockStorage, CHUNK_SIZE};
+
+    struct TestOre;
+
+    impl Ore for TestOre {
+        fn get_name(&self) -> &'static str {
+            "test_ore"
+        }
+
+        fn get_block(&self) -> Block {
+            Block::new(3)
+        }
+
+        fn get_heights(&self) -> Range<i32> {
+            0..8
+        }
+
+        fn get_vein_size(&self) -> u32 {
+            6
+        }
+
+        fn get_attempts(&self) -> u32 {
+            4
+        }
+
+        fn can_replace(&self, block: Block) -> bool {
+            block == Block::new(1)
+        }
+    }
+
+    #[test]
+    fn test_ore_veins() {
+        let generate = |coord| {
+            let mut data = BlockStorage::new(Block::new(1));
+            // Ore never replaces blocks that are not its host
+            for i in 0..CHUNK_SIZE.0 {
+                for k in 0..CHUNK_SIZE.2 {
+                    data.set((i, 0, k), Block::new(2));
+                }
+            }
+            let mut chunk_data = Some(data);
+            generate_veins(&TestOre, 0, coord, &mut chunk_data, 7);
+            chunk_data.unwrap()
+        };
+        let ores = |data: &BlockStorage| {
+            let mut ores = Vec::new();
+            for i in 0..CHUNK_SIZE.0 {
+                for j in 0..CHUNK_SIZE.1 {
+                    for k in 0..CHUNK_SIZE.2 {
+                        if data.get((i, j, k)) == TestOre.get_block() {
+                            ores.push((i, j, k));
+                        }
+                    }
+                }
+            }
+            ores
+        };
+
+        let veins = ores(&generate(ivec3(0, 0, 0)));
+        assert!(!veins.is_empty());
+        assert!(veins.iter().all(|&(_, j, _)| j > 0 && j < 8 + 6));
+        assert_eq!(veins, ores(&generate(ivec3(0, 0, 0))));
+        // Chunks outside the ore's heights get none
+        assert!(ores(&generate(ivec3(0, 1, 0))).is_empty());
+    }
+}
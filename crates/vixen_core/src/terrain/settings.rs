@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+/// How a world's terrain is generated, stored with the world in `level.json`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TerrainSettings {
+    /// Distance in blocks over which the terrain shapes of neighboring biomes are blended. Zero
+    /// turns blending off, so biome borders can have cliffs.
+    pub blend_radius: u32,
+    pub caves: CaveSettings,
+    /// Height below which open air above the terrain is filled with `sea_block`
+    pub sea_level: i32,
+    /// Code name of the fluid that fills seas. Worlds without such a block have no seas.
+    pub sea_block: String,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            blend_radius: 12,
+            caves: CaveSettings::default(),
+            sea_level: 0,
+            sea_block: "water".to_owned(),
+        }
+    }
+}
+
+/// How caves are carved out of the terrain
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct CaveSettings {
+    pub enabled: bool,
+    /// Noise value above which large "cheese" caverns are carved, between 0.0 and 1.0. Higher
+    /// values make fewer and smaller caverns.
+    pub cheese_threshold: f64,
+    /// Width of the winding "spaghetti" tunnels in noise units. Zero leaves them out.
+    pub spaghetti_width: f64,
+    /// Blocks of ground that caves leave above them, except at entrances
+    pub surface_depth: i32,
+    /// Noise value above which tunnels break through to the surface, between 0.0 and 1.0. Higher
+    /// values make entrances rarer.
+    pub entrance_threshold: f64,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cheese_threshold: 0.55,
+            spaghetti_width: 0.06,
+            surface_depth: 8,
+            entrance_threshold: 0.5,
+        }
+    }
+}
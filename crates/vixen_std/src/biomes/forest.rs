@@ -1,31 +1,53 @@
-use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-    sync::Arc,
-};
+use std::sync::Arc;
 
 use dashmap::DashMap;
-use noise::NoiseFn;
-use rand::{Rng, SeedableRng};
+use rand::Rng;
 use vixen_core::{
-    chunk_local_to_block_coord,
     loader::{ChunkData, UnfinishedChunkData, CHUNK_SIZE},
-    terrain::{get_block_from_chunk, set_block_in_chunk, Biome, Structure},
+    terrain::{
+        get_block_from_chunk, set_block_in_chunk, Biome, ChunkTerrain, ClimateRange, ColumnMask,
+        Structure, TerrainShape,
+    },
     Block, BlockCoord, ChunkCoord,
 };
 
 use crate::{
-    structures::{BrownMushroom, OakTree},
-    StandardBlocks,
+    structures::{BrownMushroom, GroundCover, OakTree},
+    StandardBlockIds, StandardBlocks,
 };
 
-pub struct ForestBiome;
+pub struct ForestBiome {
+    blocks: StandardBlockIds,
+    oak_tree: OakTree,
+    brown_mushroom: BrownMushroom,
+    ground_cover: GroundCover,
+}
+
+impl ForestBiome {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self {
+            blocks,
+            oak_tree: OakTree::new(blocks),
+            brown_mushroom: BrownMushroom::new(blocks),
+            ground_cover: GroundCover::new(blocks),
+        }
+    }
+}
 
 impl Biome for ForestBiome {
     fn get_name(&self) -> &'static str {
         "Forest"
     }
 
+    fn get_climate(&self) -> ClimateRange {
+        // Temperate and wet enough for trees, anywhere on land
+        ClimateRange {
+            temperature: -0.4..0.4,
+            humidity: 0.0..1.0,
+            continentalness: -0.2..1.0,
+        }
+    }
+
     #[inline]
     fn generate_structures(
         &self,
@@ -34,11 +56,25 @@ impl Biome for ForestBiome {
         rng: &mut rand::rngs::StdRng,
     ) {
         let chance = rng.gen::<f64>();
+        let (oak_tree, brown_mushroom, ground_cover) =
+            (&self.oak_tree, &self.brown_mushroom, &self.ground_cover);
+
+        if chance < oak_tree.get_chance() {
+            oak_tree.generate(*block_coord, in_progress, rng);
+        } else if chance - oak_tree.get_chance() < brown_mushroom.get_chance() {
+            brown_mushroom.generate(*block_coord, in_progress, rng);
+        } else if chance - oak_tree.get_chance() - brown_mushroom.get_chance()
+            < ground_cover.get_chance()
+        {
+            ground_cover.generate(*block_coord, in_progress, rng);
+        }
+    }
 
-        if chance < OakTree.get_chance() {
-            OakTree.generate(*block_coord, in_progress, rng);
-        } else if chance - OakTree.get_chance() < BrownMushroom.get_chance() {
-            BrownMushroom.generate(*block_coord, in_progress, rng);
+    fn get_terrain_shape(&self) -> TerrainShape {
+        TerrainShape {
+            base_height: 0.0,
+            height_variation: 75.0,
+            roughness: 0.0,
         }
     }
 
@@ -47,54 +83,34 @@ impl Biome for ForestBiome {
         &self,
         coord: ChunkCoord,
         chunk_data: &mut ChunkData,
-        in_progress: Arc<DashMap<ChunkCoord, UnfinishedChunkData>>,
-        noise: &Box<dyn NoiseFn<f64, 3> + Send + Sync>,
-        seed: u32,
+        columns: &ColumnMask,
+        terrain: &ChunkTerrain,
+        _seed: u32,
     ) {
-        let (x, y, z) = (coord.x, coord.y, coord.z);
-        let mut heights = ndarray::Array2::<i32>::zeros((CHUNK_SIZE.0, CHUNK_SIZE.2));
-        for i in 0..CHUNK_SIZE.0 {
-            for j in 0..CHUNK_SIZE.2 {
-                let freq = 0.01;
-                let height = 75.0
-                    * noise.get([
-                        (x * CHUNK_SIZE.0 as i32 + i as i32) as f64
-                            / (CHUNK_SIZE.0 as f32 / freq) as f64,
-                        (z * CHUNK_SIZE.2 as i32 + j as i32) as f64
-                            / (CHUNK_SIZE.2 as f32 / freq) as f64,
-                        0.0,
-                    ]);
-
-                heights[(i, j)] = height as i32;
-            }
-        }
-
+        let y = coord.y;
         for i in 0..CHUNK_SIZE.0 {
             for j in 0..CHUNK_SIZE.1 {
                 for k in 0..CHUNK_SIZE.2 {
-                    let block_coord =
-                        chunk_local_to_block_coord(&(i as i32, j as i32, k as i32), &coord);
+                    if !columns[i][k] {
+                        continue;
+                    }
                     if get_block_from_chunk(&chunk_data, (i, j, k)).unwrap_or(Block::air())
                         != Block::air()
                     {
                         continue;
                     }
-                    if heights[(i, k)] > (j as i32 + y * CHUNK_SIZE.1 as i32) {
-                        let block = match j as i32 + y * CHUNK_SIZE.1 as i32 {
+                    let height = j as i32 + y * CHUNK_SIZE.1 as i32;
+                    if terrain.is_solid(i, height, k) {
+                        let block = match terrain.height(i, k) - height {
                             // Grass layer
-                            height if heights[(i, k)] - height == 1 => StandardBlocks::Grass,
+                            1 => StandardBlocks::Grass,
                             // Dirt layer
-                            height if heights[(i, k)] - height < 5 => StandardBlocks::Dirt,
+                            depth if depth < 5 => StandardBlocks::Dirt,
                             // Stone layer
                             _ => StandardBlocks::Stone,
                         };
 
-                        set_block_in_chunk(chunk_data, (i, j, k), block.into());
-                    } else if heights[(i, k)] == (j as i32 + y * CHUNK_SIZE.1 as i32) {
-                        let mut hasher = DefaultHasher::new();
-                        (seed, coord, i, j, k).hash(&mut hasher);
-                        let mut rand = rand::rngs::StdRng::seed_from_u64(hasher.finish());
-                        self.generate_structures(&block_coord, in_progress.clone(), &mut rand);
+                        set_block_in_chunk(chunk_data, (i, j, k), self.blocks.get(block));
                     }
                 }
             }
@@ -1,35 +1,18 @@
 use vixen_core::{
-    game::BlockType,
-    loader::{register_block, Block},
+    game::{BlockType, RenderType, StateProperty},
+    loader::{Block, Registry, RegistryError},
 };
 
 pub mod biomes;
+pub mod ores;
 mod plugin;
 pub mod structures;
 
 pub use plugin::*;
 
-lazy_static::lazy_static! {
-    static ref AIR: u16 = register_block(StandardBlocks::Air);
-    static ref STONE: u16 = register_block(StandardBlocks::Stone);
-    static ref GRASS: u16 = register_block(StandardBlocks::Grass);
-    static ref DIRT: u16 = register_block(StandardBlocks::Dirt);
-    static ref COBBLESTONE: u16 = register_block(StandardBlocks::Cobblestone);
-    static ref OAK_PLANK: u16 = register_block(StandardBlocks::OakPlank);
-    static ref OAK_LOG: u16 = register_block(StandardBlocks::OakLog);
-    static ref OAK_LEAVES: u16 = register_block(StandardBlocks::OakLeaves);
-    static ref MUSHROOM_STEM: u16 = register_block(StandardBlocks::MushroomStem);
-    static ref BROWN_MUSHROOM: u16 = register_block(StandardBlocks::BrownMushroom);
-    static ref RED_MUSHROOM: u16 = register_block(StandardBlocks::RedMushroom);
-    static ref GOLD_ORE: u16 = register_block(StandardBlocks::GoldOre);
-    static ref IRON_ORE: u16 = register_block(StandardBlocks::IronOre);
-    static ref COAL_ORE: u16 = register_block(StandardBlocks::CoalOre);
-    static ref SAND: u16 = register_block(StandardBlocks::Sand);
-    static ref GRAVEL: u16 = register_block(StandardBlocks::Gravel);
-    static ref BIRCH_LEAVES: u16 = register_block(StandardBlocks::BirchLeaves);
-    static ref BIRCH_PLANK: u16 = register_block(StandardBlocks::BirchPlank);
-}
-
+/// Blocks that come with the game. Other plugins can register blocks before them, so the ids they
+/// get are looked up by code name into `StandardBlockIds`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum StandardBlocks {
     Air,
     Stone,
@@ -49,6 +32,45 @@ pub enum StandardBlocks {
     Gravel,
     BirchLeaves,
     BirchPlank,
+    OakSlab,
+    OakStairs,
+    OakFence,
+    CobblestoneSlab,
+    TallGrass,
+    Dandelion,
+    Poppy,
+    Water,
+}
+
+impl StandardBlocks {
+    pub const ALL: [StandardBlocks; 26] = [
+        StandardBlocks::Air,
+        StandardBlocks::Stone,
+        StandardBlocks::Grass,
+        StandardBlocks::Dirt,
+        StandardBlocks::Cobblestone,
+        StandardBlocks::OakPlank,
+        StandardBlocks::OakLog,
+        StandardBlocks::OakLeaves,
+        StandardBlocks::MushroomStem,
+        StandardBlocks::BrownMushroom,
+        StandardBlocks::RedMushroom,
+        StandardBlocks::GoldOre,
+        StandardBlocks::IronOre,
+        StandardBlocks::CoalOre,
+        StandardBlocks::Sand,
+        StandardBlocks::Gravel,
+        StandardBlocks::BirchLeaves,
+        StandardBlocks::BirchPlank,
+        StandardBlocks::OakSlab,
+        StandardBlocks::OakStairs,
+        StandardBlocks::OakFence,
+        StandardBlocks::CobblestoneSlab,
+        StandardBlocks::TallGrass,
+        StandardBlocks::Dandelion,
+        StandardBlocks::Poppy,
+        StandardBlocks::Water,
+    ];
 }
 
 impl BlockType for StandardBlocks {
@@ -72,6 +94,14 @@ impl BlockType for StandardBlocks {
             StandardBlocks::Gravel => "Gravel",
             StandardBlocks::BirchLeaves => "Birch Leaves",
             StandardBlocks::BirchPlank => "Birch Plank",
+            StandardBlocks::OakSlab => "Oak Slab",
+            StandardBlocks::OakStairs => "Oak Stairs",
+            StandardBlocks::OakFence => "Oak Fence",
+            StandardBlocks::CobblestoneSlab => "Cobblestone Slab",
+            StandardBlocks::TallGrass => "Tall Grass",
+            StandardBlocks::Dandelion => "Dandelion",
+            StandardBlocks::Poppy => "Poppy",
+            StandardBlocks::Water => "Water",
         }
     }
 
@@ -95,29 +125,14 @@ impl BlockType for StandardBlocks {
             StandardBlocks::Gravel => 1.,
             StandardBlocks::BirchLeaves => 1.,
             StandardBlocks::BirchPlank => 1.,
-        }
-    }
-
-    fn get_id(&self) -> u16 {
-        match self {
-            StandardBlocks::Air => *AIR,
-            StandardBlocks::Stone => *STONE,
-            StandardBlocks::Grass => *GRASS,
-            StandardBlocks::Dirt => *DIRT,
-            StandardBlocks::Cobblestone => *COBBLESTONE,
-            StandardBlocks::OakPlank => *OAK_PLANK,
-            StandardBlocks::OakLog => *OAK_LOG,
-            StandardBlocks::OakLeaves => *OAK_LEAVES,
-            StandardBlocks::MushroomStem => *MUSHROOM_STEM,
-            StandardBlocks::BrownMushroom => *BROWN_MUSHROOM,
-            StandardBlocks::RedMushroom => *RED_MUSHROOM,
-            StandardBlocks::GoldOre => *GOLD_ORE,
-            StandardBlocks::IronOre => *IRON_ORE,
-            StandardBlocks::CoalOre => *COAL_ORE,
-            StandardBlocks::Sand => *SAND,
-            StandardBlocks::Gravel => *GRAVEL,
-            StandardBlocks::BirchLeaves => *BIRCH_LEAVES,
-            StandardBlocks::BirchPlank => *BIRCH_PLANK,
+            StandardBlocks::OakSlab => 1.,
+            StandardBlocks::OakStairs => 1.,
+            StandardBlocks::OakFence => 1.,
+            StandardBlocks::CobblestoneSlab => 20.,
+            StandardBlocks::TallGrass => 0.,
+            StandardBlocks::Dandelion => 0.,
+            StandardBlocks::Poppy => 0.,
+            StandardBlocks::Water => 0.,
         }
     }
 
@@ -141,36 +156,99 @@ impl BlockType for StandardBlocks {
             StandardBlocks::Gravel => "gravel",
             StandardBlocks::BirchLeaves => "birch_leaves",
             StandardBlocks::BirchPlank => "birch_planks",
+            StandardBlocks::OakSlab => "oak_slab",
+            StandardBlocks::OakStairs => "oak_stairs",
+            StandardBlocks::OakFence => "oak_fence",
+            StandardBlocks::CobblestoneSlab => "cobblestone_slab",
+            StandardBlocks::TallGrass => "tall_grass",
+            StandardBlocks::Dandelion => "dandelion",
+            StandardBlocks::Poppy => "poppy",
+            StandardBlocks::Water => "water",
         }
     }
+
+    fn is_solid(&self) -> bool {
+        !matches!(
+            self,
+            StandardBlocks::Air
+                | StandardBlocks::TallGrass
+                | StandardBlocks::Dandelion
+                | StandardBlocks::Poppy
+                | StandardBlocks::Water
+        )
+    }
+
+    fn is_fluid(&self) -> bool {
+        matches!(self, StandardBlocks::Water)
+    }
+
+    fn get_state_properties(&self) -> &'static [StateProperty] {
+        match self {
+            StandardBlocks::OakLog => &[StateProperty::AXIS],
+            StandardBlocks::OakSlab | StandardBlocks::CobblestoneSlab => &[StateProperty::HALF],
+            StandardBlocks::OakStairs => &[StateProperty::FACING],
+            StandardBlocks::Water => &[StateProperty::LEVEL],
+            _ => &[],
+        }
+    }
+
+    fn get_render_type(&self) -> RenderType {
+        match self {
+            StandardBlocks::Air => RenderType::Invisible,
+            StandardBlocks::OakLeaves
+            | StandardBlocks::BirchLeaves
+            | StandardBlocks::TallGrass
+            | StandardBlocks::Dandelion
+            | StandardBlocks::Poppy => RenderType::Cutout,
+            StandardBlocks::Water => RenderType::Transparent,
+            _ => RenderType::Opaque,
+        }
+    }
+
+    fn is_full_cube(&self) -> bool {
+        !matches!(
+            self,
+            StandardBlocks::Air
+                | StandardBlocks::OakSlab
+                | StandardBlocks::OakStairs
+                | StandardBlocks::OakFence
+                | StandardBlocks::CobblestoneSlab
+                | StandardBlocks::TallGrass
+                | StandardBlocks::Dandelion
+                | StandardBlocks::Poppy
+        )
+    }
 }
 
-impl From<StandardBlocks> for Block {
-    fn from(block: StandardBlocks) -> Self {
-        Block { id: block.get_id() }
+/// Ids the standard blocks are registered with, for the biomes, structures and ores that place them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StandardBlockIds([u16; StandardBlocks::ALL.len()]);
+
+impl StandardBlockIds {
+    /// Looks up the id of every standard block by its code name
+    pub fn new(registry: &Registry) -> Result<Self, RegistryError> {
+        let mut ids = [0; StandardBlocks::ALL.len()];
+        for (id, block) in ids.iter_mut().zip(StandardBlocks::ALL) {
+            let name = block.get_code_name();
+            *id = registry
+                .get_block_id(name)
+                .ok_or_else(|| RegistryError::Missing(name.to_owned()))?;
+        }
+        Ok(StandardBlockIds(ids))
+    }
+
+    pub fn get(&self, block: StandardBlocks) -> Block {
+        // `StandardBlocks::ALL` lists the blocks in the order they are declared in
+        Block::new(self.0[block as usize])
     }
 }
 
-fn register_blocks() {
-    // Make lazy statics execute
-    let _ = StandardBlocks::Air.get_id();
-    let _ = StandardBlocks::Stone.get_id();
-    let _ = StandardBlocks::Grass.get_id();
-    let _ = StandardBlocks::Dirt.get_id();
-    let _ = StandardBlocks::Cobblestone.get_id();
-    let _ = StandardBlocks::OakPlank.get_id();
-    let _ = StandardBlocks::OakLog.get_id();
-    let _ = StandardBlocks::OakLeaves.get_id();
-    let _ = StandardBlocks::MushroomStem.get_id();
-    let _ = StandardBlocks::BrownMushroom.get_id();
-    let _ = StandardBlocks::RedMushroom.get_id();
-    let _ = StandardBlocks::GoldOre.get_id();
-    let _ = StandardBlocks::IronOre.get_id();
-    let _ = StandardBlocks::CoalOre.get_id();
-    let _ = StandardBlocks::Sand.get_id();
-    let _ = StandardBlocks::Gravel.get_id();
-    let _ = StandardBlocks::BirchLeaves.get_id();
-    let _ = StandardBlocks::BirchPlank.get_id();
+/// Registers every standard block and returns the ids they got
+fn register_blocks(registry: &mut Registry) -> Result<StandardBlockIds, RegistryError> {
+    for block in StandardBlocks::ALL {
+        registry.register_block(block)?;
+    }
+    StandardBlockIds::new(registry)
 }
 
 #[cfg(test)]
@@ -181,35 +259,77 @@ mod tests {
     use dashmap::DashMap;
     use rayon::{iter::IntoParallelIterator, prelude::ParallelIterator};
 
-    use vixen_core::{loader::register_biome, terrain::TerrainGenerator};
+    use vixen_core::{
+        game::{BlockType, RenderType},
+        loader::{Block, Registry, RegistryError},
+        terrain::{TerrainGenerator, TerrainSettings},
+    };
 
-    use crate::biomes::ForestBiome;
+    use crate::{biomes::ForestBiome, register_blocks, StandardBlocks};
+
+    struct Glass;
+
+    impl BlockType for Glass {
+        fn get_name(&self) -> &str {
+            "Glass"
+        }
+
+        fn get_durability(&self) -> f32 {
+            0.3
+        }
+
+        fn get_code_name(&self) -> &str {
+            "glass"
+        }
+
+        fn get_render_type(&self) -> RenderType {
+            RenderType::Cutout
+        }
+    }
+
+    #[test]
+    fn test_standard_block_ids() {
+        // A block registered by another plugin first moves the standard blocks to other ids
+        let mut registry = Registry::default();
+        registry.register_block(Glass).unwrap();
+        let blocks = register_blocks(&mut registry).unwrap();
+        for block in StandardBlocks::ALL {
+            let id = registry.get_block_id(block.get_code_name()).unwrap();
+            assert_eq!(blocks.get(block), Block::new(id));
+        }
+        assert_eq!(blocks.get(StandardBlocks::Stone), Block::new(2));
+
+        // A standard block that is already taken is an error rather than a panic
+        let mut registry = Registry::default();
+        registry.register_block(StandardBlocks::Dirt).unwrap();
+        assert_eq!(
+            register_blocks(&mut registry),
+            Err(RegistryError::Duplicate("dirt".to_owned()))
+        );
+    }
 
     #[test]
     fn test_chunk_generation_perf() {
         // Register biome
-        register_biome(ForestBiome);
+        let mut registry = Registry::default();
+        let blocks = register_blocks(&mut registry).unwrap();
+        registry.register_biome(ForestBiome::new(blocks)).unwrap();
+        registry.freeze();
 
-        let generator = TerrainGenerator::new(0);
+        let generator = TerrainGenerator::new(0, TerrainSettings::default(), Arc::new(registry));
         let in_progress = Arc::new(DashMap::new());
 
         // Start timing
         let start = std::time::Instant::now();
 
-        (-10..=10)
-            .into_par_iter()
-            .for_each(|x| {
-                (-10..=10)
-                    .into_par_iter()
-                    .for_each(|y| {
-                        (-10..=10)
-                            .into_par_iter()
-                            .for_each(|z| {
-                                let coord = ivec3(x, y, z);
-                                let _ = generator.generate_chunk(0, coord, in_progress.clone());
-                            });
-                    });
+        (-10..=10).into_par_iter().for_each(|x| {
+            (-10..=10).into_par_iter().for_each(|y| {
+                (-10..=10).into_par_iter().for_each(|z| {
+                    let coord = ivec3(x, y, z);
+                    let _ = generator.generate_chunk(0, coord, in_progress.clone());
+                });
             });
+        });
 
         // End timing
         let end = std::time::Instant::now();
@@ -0,0 +1,42 @@
+use std::ops::Range;
+
+use vixen_core::{terrain::Ore, Block};
+
+use crate::{StandardBlockIds, StandardBlocks};
+
+/// Large, common veins of coal, from deep underground up into hills
+pub struct CoalVeins {
+    blocks: StandardBlockIds,
+}
+
+impl CoalVeins {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self { blocks }
+    }
+}
+
+impl Ore for CoalVeins {
+    fn get_name(&self) -> &'static str {
+        "coal_ore"
+    }
+
+    fn get_block(&self) -> Block {
+        self.blocks.get(StandardBlocks::CoalOre)
+    }
+
+    fn get_heights(&self) -> Range<i32> {
+        -128..64
+    }
+
+    fn get_vein_size(&self) -> u32 {
+        14
+    }
+
+    fn get_attempts(&self) -> u32 {
+        16
+    }
+
+    fn can_replace(&self, block: Block) -> bool {
+        block == self.blocks.get(StandardBlocks::Stone)
+    }
+}
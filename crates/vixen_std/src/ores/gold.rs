@@ -0,0 +1,42 @@
+use std::ops::Range;
+
+use vixen_core::{terrain::Ore, Block};
+
+use crate::{StandardBlockIds, StandardBlocks};
+
+/// Small, rare veins of gold deep underground
+pub struct GoldVeins {
+    blocks: StandardBlockIds,
+}
+
+impl GoldVeins {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self { blocks }
+    }
+}
+
+impl Ore for GoldVeins {
+    fn get_name(&self) -> &'static str {
+        "gold_ore"
+    }
+
+    fn get_block(&self) -> Block {
+        self.blocks.get(StandardBlocks::GoldOre)
+    }
+
+    fn get_heights(&self) -> Range<i32> {
+        -128..-32
+    }
+
+    fn get_vein_size(&self) -> u32 {
+        6
+    }
+
+    fn get_attempts(&self) -> u32 {
+        3
+    }
+
+    fn can_replace(&self, block: Block) -> bool {
+        block == self.blocks.get(StandardBlocks::Stone)
+    }
+}
@@ -0,0 +1,42 @@
+use std::ops::Range;
+
+use vixen_core::{terrain::Ore, Block};
+
+use crate::{StandardBlockIds, StandardBlocks};
+
+/// Medium veins of iron below and around sea level
+pub struct IronVeins {
+    blocks: StandardBlockIds,
+}
+
+impl IronVeins {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self { blocks }
+    }
+}
+
+impl Ore for IronVeins {
+    fn get_name(&self) -> &'static str {
+        "iron_ore"
+    }
+
+    fn get_block(&self) -> Block {
+        self.blocks.get(StandardBlocks::IronOre)
+    }
+
+    fn get_heights(&self) -> Range<i32> {
+        -128..32
+    }
+
+    fn get_vein_size(&self) -> u32 {
+        8
+    }
+
+    fn get_attempts(&self) -> u32 {
+        10
+    }
+
+    fn can_replace(&self, block: Block) -> bool {
+        block == self.blocks.get(StandardBlocks::Stone)
+    }
+}
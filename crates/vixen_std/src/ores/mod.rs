@@ -0,0 +1,7 @@
+mod coal;
+mod gold;
+mod iron;
+
+pub use coal::*;
+pub use gold::*;
+pub use iron::*;
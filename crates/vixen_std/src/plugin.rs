@@ -1,7 +1,11 @@
-use bevy::prelude::Plugin;
-use vixen_core::loader::register_biome;
+use bevy::prelude::*;
+use vixen_core::loader::Registry;
 
-use crate::{biomes::ForestBiome, register_blocks};
+use crate::{
+    biomes::ForestBiome,
+    ores::{CoalVeins, GoldVeins, IronVeins},
+    register_blocks,
+};
 pub struct StandardPlugin;
 
 impl Plugin for StandardPlugin {
@@ -10,8 +14,26 @@ impl Plugin for StandardPlugin {
     }
 }
 
-fn register_everything() {
-    register_biome(ForestBiome);
+fn register_everything(mut registry: ResMut<Registry>) {
+    let blocks = match register_blocks(&mut registry) {
+        Ok(blocks) => blocks,
+        Err(e) => {
+            error!("Failed to register blocks: {}", e);
+            return;
+        }
+    };
 
-    register_blocks();
+    if let Err(e) = registry.register_biome(ForestBiome::new(blocks)) {
+        error!("Failed to register biome: {}", e);
+    }
+
+    for result in [
+        registry.register_ore(CoalVeins::new(blocks)),
+        registry.register_ore(IronVeins::new(blocks)),
+        registry.register_ore(GoldVeins::new(blocks)),
+    ] {
+        if let Err(e) = result {
+            error!("Failed to register ore: {}", e);
+        }
+    }
 }
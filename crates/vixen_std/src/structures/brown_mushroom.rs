@@ -1,12 +1,17 @@
 use bevy::math::ivec3;
-use vixen_core::{
-    terrain::{set_block_in_neighborhood, Structure},
-    Block,
-};
+use vixen_core::terrain::{set_block_in_neighborhood, Structure};
 
-use crate::StandardBlocks;
+use crate::{StandardBlockIds, StandardBlocks};
 
-pub struct BrownMushroom;
+pub struct BrownMushroom {
+    blocks: StandardBlockIds,
+}
+
+impl BrownMushroom {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self { blocks }
+    }
+}
 
 const BROWN_MUSHROOM_HEIGHT: i32 = 6;
 const BROWN_MUSHROOM_RADIUS: i32 = 3;
@@ -30,13 +35,13 @@ impl Structure for BrownMushroom {
         for i in 0..BROWN_MUSHROOM_HEIGHT {
             set_block_in_neighborhood(
                 ivec3(x, y + i, z),
-                Block::from(StandardBlocks::MushroomStem),
+                self.blocks.get(StandardBlocks::MushroomStem),
                 in_progress.clone(),
             );
         }
 
         // Build the cap
-        let block = Block::from(StandardBlocks::BrownMushroom);
+        let block = self.blocks.get(StandardBlocks::BrownMushroom);
         for j in 0..BROWN_MUSHROOM_RADIUS {
             for k in 0..BROWN_MUSHROOM_RADIUS {
                 if j == BROWN_MUSHROOM_RADIUS - 1 && k == BROWN_MUSHROOM_RADIUS - 1 {
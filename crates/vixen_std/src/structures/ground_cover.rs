@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use rand::Rng;
+use vixen_core::loader::UnfinishedChunkData;
+use vixen_core::terrain::{set_block_in_neighborhood, Structure};
+use vixen_core::*;
+
+use crate::{StandardBlockIds, StandardBlocks};
+
+const GROUND_COVER_CHANCE: f64 = 0.12;
+/// Share of ground cover that is a flower rather than tall grass
+const FLOWER_CHANCE: f64 = 0.1;
+
+/// A single plant on top of the ground
+pub struct GroundCover {
+    blocks: StandardBlockIds,
+}
+
+impl GroundCover {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self { blocks }
+    }
+}
+
+impl Structure for GroundCover {
+    fn get_chance(&self) -> f64 {
+        GROUND_COVER_CHANCE
+    }
+
+    fn generate(
+        &self,
+        position: BlockCoord,
+        in_progress: Arc<DashMap<ChunkCoord, UnfinishedChunkData>>,
+        rng: &mut rand::rngs::StdRng,
+    ) {
+        let plant = if rng.gen::<f64>() < FLOWER_CHANCE {
+            if rng.gen() {
+                StandardBlocks::Dandelion
+            } else {
+                StandardBlocks::Poppy
+            }
+        } else {
+            StandardBlocks::TallGrass
+        };
+        set_block_in_neighborhood(position, self.blocks.get(plant), in_progress);
+    }
+}
@@ -1,5 +1,7 @@
 mod brown_mushroom;
+mod ground_cover;
 mod oak_tree;
 
 pub use brown_mushroom::*;
+pub use ground_cover::*;
 pub use oak_tree::*;
@@ -7,7 +7,7 @@ use vixen_core::loader::UnfinishedChunkData;
 use vixen_core::terrain::{set_block_in_neighborhood, Structure};
 use vixen_core::*;
 
-use crate::StandardBlocks;
+use crate::{StandardBlockIds, StandardBlocks};
 
 const OAK_CHANCE: f64 = 0.005;
 const MIN_OAK_TREE_HEIGHT: usize = 5;
@@ -15,7 +15,15 @@ const MAX_OAK_TREE_HEIGHT: usize = 8;
 const OAK_LEAVES_RADIUS: i32 = 3;
 const OAK_LEAVES_HEIGHT: i32 = 4;
 
-pub struct OakTree;
+pub struct OakTree {
+    blocks: StandardBlockIds,
+}
+
+impl OakTree {
+    pub fn new(blocks: StandardBlockIds) -> Self {
+        Self { blocks }
+    }
+}
 
 impl Structure for OakTree {
     fn get_chance(&self) -> f64 {
@@ -32,7 +40,7 @@ impl Structure for OakTree {
         let leaves_height = trunk_height - 3;
 
         // Build the leaves
-        let block = Block::from(StandardBlocks::OakLeaves);
+        let block = self.blocks.get(StandardBlocks::OakLeaves);
         for y in 1..OAK_LEAVES_HEIGHT - 1 {
             for x in 0..OAK_LEAVES_RADIUS {
                 for z in 0..OAK_LEAVES_RADIUS {
@@ -113,7 +121,7 @@ impl Structure for OakTree {
         }
 
         // Build the trunk
-        let block = Block::from(StandardBlocks::OakLog);
+        let block = self.blocks.get(StandardBlocks::OakLog);
         for y in 0..trunk_height {
             set_block_in_neighborhood(position + ivec3(0, y as i32, 0), block, in_progress.clone());
         }
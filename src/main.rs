@@ -1,5 +1,9 @@
 use bevy::prelude::*;
-use vixen_core::{ui::UiPlugin, DebugPlugin, GamePlugin, GameState, MenuPlugin, loader::DataPack};
+use vixen_core::{
+    loader::{DataPack, WorldName},
+    ui::UiPlugin,
+    DebugPlugin, GamePlugin, GameState, MenuPlugin,
+};
 use vixen_std::StandardPlugin;
 
 fn main() {
@@ -13,5 +17,6 @@ fn main() {
         .add_plugin(DebugPlugin)
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(DataPack("ghibli".to_string()))
+        .insert_resource(WorldName("world".to_string()))
         .run();
 }